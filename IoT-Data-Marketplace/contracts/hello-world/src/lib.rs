@@ -1,6 +1,7 @@
 #![allow(non_snake_case)]
+#![allow(clippy::too_many_arguments, clippy::needless_return)]
 #![no_std]
-use soroban_sdk::{contract, contracttype, contractimpl, log, Env, Symbol, String, symbol_short, Address, Vec};
+use soroban_sdk::{contract, contractclient, contracttype, contractimpl, log, token, xdr::ToXdr, Env, Symbol, String, symbol_short, Address, Bytes, BytesN, Map, Vec};
 
 // Define asset status to track leased assets
 #[contracttype]
@@ -9,7 +10,7 @@ pub struct AssetStats {
     pub available: u64,   // Count of assets available for lease
     pub leased: u64,      // Count of currently leased assets
     pub registered: u64,  // Total count of registered assets
-    pub total_revenue: u64 // Total revenue generated from leases (in stroop - millionth of XLM)
+    pub revenue: Map<Address, u64> // Revenue generated from leases per pricing token (in the token's base units)
 }
 
 // Reference to the AssetStats struct - fixed symbol length
@@ -26,6 +27,39 @@ pub enum PaymentModel {
     PayPerUse
 }
 
+// Cancellation policies: share of the unused lease cost refunded when the lessee ends early
+#[contracttype]
+#[derive(Clone, PartialEq)]
+pub enum CancellationPolicy {
+    Flexible,    // Full refund of the unused portion
+    Moderate,    // Half of the unused portion refunded
+    Strict       // No refund
+}
+
+// Price per billing unit (hour, day, ...) charged from a given unit of the lease onward
+#[contracttype]
+#[derive(Clone)]
+pub struct PriceTier {
+    pub from_unit: u64,  // First billing unit this price applies to (units before it use earlier tiers)
+    pub price: u64       // Price per billing unit in this tier
+}
+
+// Percentage off the whole lease for durations of at least min_duration seconds
+#[contracttype]
+#[derive(Clone)]
+pub struct DurationDiscount {
+    pub min_duration: u64,
+    pub percent_off: u64
+}
+
+// Named channel of an asset (e.g. raw telemetry, control commands) that leases can be scoped to
+#[contracttype]
+#[derive(Clone)]
+pub struct Capability {
+    pub name: Symbol,
+    pub price: u64       // Price per billing unit added to the lease cost when granted
+}
+
 // Define the Asset structure for registration
 #[contracttype]
 #[derive(Clone)]
@@ -36,12 +70,26 @@ pub struct Asset {
     pub description: String,
     pub asset_type: String,  // "Physical" or "Digital"
     pub location: String,    // Geographic location or "Digital"
-    pub price: u64,          // Price in stroop (millionth of XLM)
+    pub price: u64,          // Price in base units of the pricing token (or reference-currency units at the token's decimals)
+    pub token: Address,      // Pricing token (from the admin allowlist)
+    pub priced_in_fiat: bool,// Price is in the oracle's reference currency, converted at lease time
     pub payment_model: PaymentModel,
     pub is_available: bool,
     pub created_time: u64,
     pub quality_guarantee: String,
-    pub rating: u64          // Rating out of 100
+    pub rating: u64,         // Rating out of 100
+    pub deposit: u64,        // Security deposit required from lessees (Physical assets only)
+    pub streaming: bool,     // Leases pay the lessor per second instead of up front
+    pub cancellation_policy: CancellationPolicy,
+    pub termination_penalty: u64, // Percentage of total cost paid to the lessee when the lessor ends early
+    pub price_tiers: Vec<PriceTier>,            // Later-unit prices, ascending by from_unit (price applies before the first)
+    pub duration_discounts: Vec<DurationDiscount>, // Ascending by min_duration; the longest qualifying one applies
+    pub requires_approval: bool, // Lease requests are held in escrow until the owner approves them
+    pub requires_credential: bool, // Lessees must hold credential_type from the attestation contract
+    pub credential_type: Symbol,
+    pub capabilities: Vec<Capability>, // Channels leases can be scoped to (empty: leases grant full access)
+    pub allows_sublease: bool, // Lessees may resell their lease to sublessees
+    pub sublease_share: u64    // Percentage of sublease proceeds paid to the owner
 }
 
 // Mapping asset_id to Asset
@@ -53,6 +101,36 @@ pub enum AssetRegistry {
 // Reference to the asset counter for unique IDs
 const ASSET_COUNTER: Symbol = symbol_short!("ASSET_CNT");
 
+// Owner-issued discount code, stored under the sha256 hash of the code
+#[contracttype]
+#[derive(Clone)]
+pub struct Coupon {
+    pub owner: Address,
+    pub owner_wide: bool,  // Valid for all of the owner's assets (asset_id is ignored)
+    pub asset_id: u64,
+    pub percent_off: u64,
+    pub max_uses: u32,
+    pub uses: u32,
+    pub expiry: u64        // Timestamp after which the coupon can no longer be redeemed
+}
+
+// Mapping (owner, code hash) to Coupon
+#[contracttype]
+pub enum CouponRegistry {
+    Coupon(Address, BytesN<32>)
+}
+
+// Lessee access lists, kept per asset and per owner (owner lists apply to all of the owner's assets)
+#[contracttype]
+pub enum AccessListRegistry {
+    AssetAllowed(u64, Address),
+    AssetBlocked(u64, Address),
+    AssetAllowlistOnly(u64),
+    OwnerAllowed(Address, Address),
+    OwnerBlocked(Address, Address),
+    OwnerAllowlistOnly(Address)
+}
+
 // Define a Lease structure to track active leases
 #[contracttype]
 #[derive(Clone)]
@@ -64,21 +142,151 @@ pub struct Lease {
     pub start_time: u64,
     pub end_time: u64,
     pub total_cost: u64,
+    pub token: Address,      // Token the lease is priced and escrowed in
     pub is_active: bool,
     pub is_paid: bool,
-    pub access_key: String,  // Encrypted access key for the asset
-    pub dispute_raised: bool
+    pub encryption_key: BytesN<32>, // Lessee's X25519 public key access keys are encrypted to (zero if unset)
+    pub key_version: u32,    // Number of access keys delivered by the lessor (0 if none yet)
+    pub access_revoked: bool,
+    pub signing_key: BytesN<32>, // Lessee's ed25519 key for signing off-chain access tokens (zero if unset)
+    pub scopes: Vec<Symbol>, // Capabilities granted (empty: full access to an asset without capabilities)
+    pub parent_lease_id: u64, // Lease this one subleases (0 for direct leases); lessor is the reselling lessee
+    pub owner_share: u64,    // Percentage of the lessor's proceeds paid to the asset owner (subleases only)
+    pub dispute_raised: bool,
+    pub dispute_id: u64,     // Latest dispute raised on this lease (0 if none)
+    pub deposit: u64,        // Security deposit held in escrow
+    pub deposit_settled: bool,
+    pub closed_time: u64,    // When the lease was closed (starts the damage claim window)
+    pub is_streaming: bool,  // Lessor's share accrues linearly between start_time and end_time
+    pub released: u64,       // Escrow already released to the lessor while streaming
+    pub cancellation_policy: CancellationPolicy, // Policy agreed at lease creation
+    pub termination_penalty: u64,
+    pub auto_renew: bool,    // Subscription renews from the lessee's token allowance
+    pub renewal_period: u64, // Length of each subscription period in seconds
+    pub is_pending: bool,    // Request awaiting the owner's approval (payment already escrowed)
+    pub approval_deadline: u64 // Pending requests not approved by this timestamp can be refunded by anyone
 }
 
-// Mapping lease_id to Lease
+// Mapping lease_id to Lease, and lease_id to its multi-signature release settings
 #[contracttype] 
 pub enum LeaseRegistry { 
-    Lease(u64)
+    Lease(u64),
+    Release(u64),
+    AccessKey(u64),  // Current access key ciphertext, kept out of the Lease returned by public views
+    Active(u64, Address),  // (asset_id, lessee) -> lease_id of the lessee's current lease of the asset
+    RevokedToken(u64, u64), // (lease_id, nonce) of a revoked access token, kept in persistent storage until it expires
+    Subleases(u64)         // Subleases created under a lease
+}
+
+// Off-chain access token signed by the lessee; the signed message is the marketplace contract address
+// followed by the token, both XDR-encoded, so a token cannot be replayed against another deployment
+#[contracttype]
+#[derive(Clone)]
+pub struct AccessToken {
+    pub lease_id: u64,
+    pub asset_id: u64,
+    pub lessee: Address,
+    pub expiry: u64,
+    pub nonce: u64
+}
+
+// Result of an access check: the lease granting access, its scopes and when access expires
+#[contracttype]
+#[derive(Clone)]
+pub struct AccessGrant {
+    pub lease_id: u64,
+    pub scopes: Vec<Symbol>,  // Empty when the lease grants full access to the asset
+    pub expires_at: u64
+}
+
+// Define multi-signature escrow release among lessor, lessee and arbitrator
+#[contracttype]
+#[derive(Clone)]
+pub struct MultiSigRelease {
+    pub required_approvals: u32,       // M of the 3 signers needed to release escrow
+    pub arbitrator: Address,           // Third signer alongside lessor and lessee
+    pub approvals: Map<Address, u64>   // Signer -> refund percentage to the lessee they approved
 }
 
 // Reference to the lease counter for unique IDs
 const LEASE_COUNTER: Symbol = symbol_short!("LEASE_CNT");
 
+// Time a subscription stays active after a failed renewal before it lapses (3 days)
+const SUBSCRIPTION_GRACE_PERIOD: u64 = 259200;
+
+// Time an owner has to approve or decline a lease request (2 days)
+const APPROVAL_WINDOW: u64 = 172800;
+
+// Approximate ledger close time, for keeping token revocations alive until the token expires
+const LEDGER_CLOSE_TIME: u64 = 5;
+
+// English auction for a fixed future lease window of an asset
+#[contracttype]
+#[derive(Clone)]
+pub struct Auction {
+    pub auction_id: u64,
+    pub asset_id: u64,
+    pub seller: Address,
+    pub token: Address,        // Bids are escrowed in the asset's pricing token
+    pub lease_start: u64,      // Start of the auctioned lease window
+    pub duration: u64,
+    pub reserve_price: u64,
+    pub deposit: u64,          // Security deposit escrowed with each bid
+    pub end_time: u64,         // Bidding closes at this timestamp
+    pub has_bid: bool,
+    pub highest_bidder: Address,
+    pub highest_bid: u64,
+    pub is_settled: bool,
+    pub lease_id: u64          // Lease created for the winner (0 if none)
+}
+
+// Mapping auction_id to Auction
+#[contracttype]
+pub enum AuctionRegistry {
+    Auction(u64)
+}
+
+// Reference to the auction counter for unique IDs
+const AUCTION_COUNTER: Symbol = symbol_short!("AUC_CNT");
+
+// Negotiation state of an offer
+#[contracttype]
+#[derive(Clone, PartialEq)]
+pub enum OfferStatus {
+    Pending,     // Awaiting the owner's response
+    Countered,   // Owner proposed new terms, awaiting the lessee
+    Accepted,
+    Rejected
+}
+
+// Lessee offer for a lease on negotiated terms, with the price escrowed
+#[contracttype]
+#[derive(Clone)]
+pub struct Offer {
+    pub offer_id: u64,
+    pub asset_id: u64,
+    pub lessee: Address,
+    pub owner: Address,
+    pub token: Address,
+    pub price: u64,         // Total lease price under the current terms
+    pub start: u64,
+    pub duration: u64,
+    pub expiry: u64,        // Current terms must be accepted before this timestamp
+    pub deposit: u64,
+    pub escrowed: u64,      // Amount held from the lessee (offered price plus deposit)
+    pub status: OfferStatus,
+    pub lease_id: u64       // Lease created on acceptance (0 if none)
+}
+
+// Mapping offer_id to Offer
+#[contracttype]
+pub enum OfferRegistry {
+    Offer(u64)
+}
+
+// Reference to the offer counter for unique IDs
+const OFFER_COUNTER: Symbol = symbol_short!("OFFER_CNT");
+
 // Define a Review structure to track user reviews
 #[contracttype]
 #[derive(Clone)]
@@ -100,6 +308,227 @@ pub enum ReviewRegistry {
 // Reference to the review counter for unique IDs - fixed symbol length
 const REVIEW_COUNTER: Symbol = symbol_short!("REV_CNT");
 
+// Reference to the marketplace admin address
+const ADMIN: Symbol = symbol_short!("ADMIN");
+
+// Define a Dispute structure to track arbitration of a lease
+#[contracttype]
+#[derive(Clone)]
+pub struct Dispute {
+    pub dispute_id: u64,
+    pub lease_id: u64,
+    pub raised_by: Address,
+    pub arbitrator: Address,     // Arbitrator assigned to decide the dispute
+    pub raised_time: u64,
+    pub is_resolved: bool,
+    pub refund_percentage: u64,  // Refund awarded to the lessee once resolved
+    pub jury: Vec<Address>,      // Jurors deciding the dispute (empty if decided by the arbitrator)
+    pub commit_deadline: u64,    // End of the jury commit phase
+    pub reveal_deadline: u64,    // End of the jury reveal phase
+    pub responded: bool,         // Whether the counterparty submitted evidence in time
+    pub response_deadline: u64,  // Deadline for the counterparty to respond with evidence
+    pub resolution_deadline: u64,// Deadline for a ruling before the fallback policy applies
+    pub ruling_time: u64,        // When the current ruling was made (starts the appeal window)
+    pub is_appealed: bool,
+    pub appellant: Address,      // Party that appealed (only meaningful once is_appealed is set)
+    pub appeal_bond: i128,       // Bond posted by the appellant
+    pub appealed_refund: u64,    // Refund percentage of the ruling under appeal
+    pub is_settled: bool,        // Whether escrow has been paid out and the lease closed
+    pub is_damage_claim: bool    // Damage claim against the security deposit rather than the lease escrow
+}
+
+// Define an Evidence structure submitted by the parties to a dispute
+#[contracttype]
+#[derive(Clone)]
+pub struct Evidence {
+    pub submitter: Address,
+    pub content: String,         // Evidence reference (e.g. IPFS hash or URL)
+    pub submitted_time: u64
+}
+
+// Mapping dispute_id to Dispute, and dispute_id to the evidence submitted for it
+#[contracttype]
+pub enum DisputeRegistry {
+    Dispute(u64),
+    Evidence(u64)
+}
+
+// Reference to the dispute counter for unique IDs
+const DISPUTE_COUNTER: Symbol = symbol_short!("DISP_CNT");
+
+// Define arbitrator statistics exposed to users choosing to trust the process
+#[contracttype]
+#[derive(Clone)]
+pub struct ArbitratorStats {
+    pub cases_decided: u64,
+    pub total_refund: u64,       // Sum of refund percentages awarded
+    pub average_refund: u64      // Average refund percentage awarded
+}
+
+// Mapping arbitrator address to ArbitratorStats
+#[contracttype]
+pub enum ArbitratorRegistry {
+    Stats(Address)
+}
+
+// Reference to the list of registered arbitrators
+const ARBITRATORS: Symbol = symbol_short!("ARBITERS");
+
+// Reference to the round-robin cursor used to assign arbitrators
+const ARB_CURSOR: Symbol = symbol_short!("ARB_NEXT");
+
+// Time after which the admin may resolve a dispute in place of the assigned arbitrator (7 days)
+const ARBITRATION_TIMEOUT: u64 = 604800;
+
+// Outcome applied to escrow when nobody rules on a dispute in time
+#[contracttype]
+#[derive(Clone, PartialEq)]
+pub enum FallbackPolicy {
+    RefundLessee,      // Full refund to the lessee
+    ReleaseToLessor,   // Full payment to the lessor
+    Split              // Escrow split evenly between both parties
+}
+
+// Define the deadlines and fallback policy applied to disputes
+#[contracttype]
+#[derive(Clone)]
+pub struct DisputeConfig {
+    pub response_window: u64,    // Seconds the counterparty has to respond with evidence
+    pub resolution_window: u64,  // Seconds the arbitrator or jury has to rule
+    pub fallback_policy: FallbackPolicy,
+    pub appeal_window: u64,      // Seconds after a ruling during which it can be appealed
+    pub appeal_bond_percentage: u64, // Appeal bond as a percentage of the disputed amount
+    pub damage_claim_window: u64 // Seconds after a lease closes during which the lessor can claim the deposit
+}
+
+// Reference to the DisputeConfig struct
+const DISPUTE_CONFIG: Symbol = symbol_short!("DISP_CFG");
+
+// Default dispute windows (3 days to respond, 14 days to rule, 3 days to appeal)
+const DEFAULT_RESPONSE_WINDOW: u64 = 259200;
+const DEFAULT_RESOLUTION_WINDOW: u64 = 1209600;
+const DEFAULT_APPEAL_WINDOW: u64 = 259200;
+
+// Default appeal bond (10% of the disputed amount)
+const DEFAULT_APPEAL_BOND_PERCENTAGE: u64 = 10;
+
+// Default damage claim window for security deposits (3 days)
+const DEFAULT_DAMAGE_CLAIM_WINDOW: u64 = 259200;
+
+// Reference to the default pricing token for new assets (native XLM asset contract)
+const PAYMENT_TOKEN: Symbol = symbol_short!("PAY_TOKEN");
+
+// Reference to the allowlist of tokens assets can be priced in
+const ALLOWED_TOKENS: Symbol = symbol_short!("TOKENS");
+
+// Reference to the default platform fee in basis points
+const PLATFORM_FEE: Symbol = symbol_short!("FEE_BPS");
+
+// Mapping asset type to its platform fee override in basis points, and token to accrued treasury fees
+#[contracttype]
+pub enum FeeRegistry {
+    AssetType(String),
+    Treasury(Address)
+}
+
+// Maximum platform fee (100% in basis points)
+const MAX_FEE_BPS: u32 = 10000;
+
+// Asset identifier used by SEP-40 price oracles
+#[contracttype]
+#[derive(Clone)]
+pub enum OracleAsset {
+    Stellar(Address),
+    Other(Symbol)
+}
+
+// Price record returned by SEP-40 price oracles
+#[contracttype]
+#[derive(Clone)]
+pub struct PriceData {
+    pub price: i128,         // Price of one whole token in the reference currency, scaled by decimals()
+    pub timestamp: u64
+}
+
+// SEP-40 price oracle interface used to convert reference-currency prices
+#[contractclient(name = "PriceOracleClient")]
+pub trait PriceOracle {
+    fn lastprice(env: Env, asset: OracleAsset) -> Option<PriceData>;
+    fn decimals(env: Env) -> u32;
+}
+
+// Define the price oracle configuration
+#[contracttype]
+#[derive(Clone)]
+pub struct OracleConfig {
+    pub oracle: Address,
+    pub max_age: u64         // Maximum age in seconds of a price used for conversion
+}
+
+// Reference to the OracleConfig struct
+const ORACLE_CONFIG: Symbol = symbol_short!("ORACLE");
+
+// Identity attestation interface used to gate leasing on lessee credentials
+#[contractclient(name = "AttestationClient")]
+pub trait Attestation {
+    fn has_credential(env: Env, address: Address, credential_type: Symbol) -> bool;
+}
+
+// Reference to the attestation contract address
+const ATTESTATION: Symbol = symbol_short!("ATTESTOR");
+
+// Mapping holder address to the balances (token -> amount) it can withdraw
+#[contracttype]
+pub enum ClaimRegistry {
+    Claimable(Address)
+}
+
+// Define the jury configuration used for high-value disputes
+#[contracttype]
+#[derive(Clone)]
+pub struct JuryConfig {
+    pub stake_token: Address,    // Token jurors stake to be eligible
+    pub min_stake: i128,         // Minimum stake required to be selected
+    pub value_threshold: u64,    // Leases with total_cost above this go to a jury
+    pub panel_size: u32,         // Number of jurors selected per dispute
+    pub commit_period: u64,      // Seconds jurors have to commit their votes
+    pub reveal_period: u64,      // Seconds jurors have to reveal after the commit phase
+    pub slash_percentage: u64    // Share of stake slashed from jurors voting against the majority
+}
+
+// Reference to the JuryConfig struct
+const JURY_CONFIG: Symbol = symbol_short!("JURY_CFG");
+
+// Define a Juror structure to track staked jurors
+#[contracttype]
+#[derive(Clone)]
+pub struct Juror {
+    pub stake: i128,
+    pub active_cases: u32        // Pending disputes the juror sits on (stake is locked meanwhile)
+}
+
+// Define a JuryVote structure for commit-reveal voting
+#[contracttype]
+#[derive(Clone)]
+pub struct JuryVote {
+    pub commitment: BytesN<32>,  // sha256(refund_percentage || salt || juror)
+    pub revealed: bool,
+    pub refund_percentage: u64
+}
+
+// Mapping juror address to Juror, and (dispute_id, juror) to JuryVote
+#[contracttype]
+pub enum JuryRegistry {
+    Juror(Address),
+    Vote(u64, Address)
+}
+
+// Reference to the list of staked jurors
+const JURORS: Symbol = symbol_short!("JURORS");
+
+// Maximum distance (in percentage points) from the median for a vote to count as the majority
+const JURY_TOLERANCE: u64 = 10;
+
 // Main contract definition
 #[contract]
 pub struct IoTMarketplace;
@@ -108,13 +537,23 @@ pub struct IoTMarketplace;
 impl IoTMarketplace {
     
     // Initialize the marketplace
-    pub fn initialize(env: Env) {
+    pub fn initialize(env: Env, admin: Address) {
+        // Prevent the admin from being replaced by a second initialization
+        if env.storage().instance().has(&ADMIN) {
+            log!(&env, "Marketplace already initialized");
+            panic!("Marketplace already initialized");
+        }
+        
+        // Verify the caller is the admin
+        admin.require_auth();
+        env.storage().instance().set(&ADMIN, &admin);
+        
         // Set initial asset stats
         let asset_stats = AssetStats {
             available: 0,
             leased: 0,
             registered: 0,
-            total_revenue: 0
+            revenue: Map::new(&env)
         };
         
         // Store initial stats
@@ -124,6 +563,11 @@ impl IoTMarketplace {
         env.storage().instance().set(&ASSET_COUNTER, &0u64);
         env.storage().instance().set(&LEASE_COUNTER, &0u64);
         env.storage().instance().set(&REVIEW_COUNTER, &0u64);
+        env.storage().instance().set(&DISPUTE_COUNTER, &0u64);
+        
+        // Start with no arbitrators - disputes fall back to the admin
+        env.storage().instance().set(&ARBITRATORS, &Vec::<Address>::new(&env));
+        env.storage().instance().set(&ARB_CURSOR, &0u64);
         
         // Set contract TTL
         env.storage().instance().extend_ttl(10000, 10000);
@@ -162,11 +606,25 @@ impl IoTMarketplace {
             asset_type,
             location,
             price,
+            token: Self::default_token(&env),
+            priced_in_fiat: false,
             payment_model,
             is_available: true,
             created_time: time,
             quality_guarantee,
-            rating: 0  // Initial rating
+            rating: 0,  // Initial rating
+            deposit: 0,
+            streaming: false,
+            cancellation_policy: CancellationPolicy::Strict,
+            termination_penalty: 0,
+            price_tiers: Vec::new(&env),
+            duration_discounts: Vec::new(&env),
+            requires_approval: false,
+            requires_credential: false,
+            credential_type: symbol_short!(""),
+            capabilities: Vec::new(&env),
+            allows_sublease: false,
+            sublease_share: 0
         };
         
         // Update asset stats
@@ -245,7 +703,10 @@ impl IoTMarketplace {
         asset_id: u64,
         lessee: Address,
         duration: u64,  // Duration in seconds
-        access_key: String
+        encryption_key: BytesN<32>,  // X25519 public key the lessor encrypts the access key to
+        max_payment: u64,  // Maximum total cost the lessee accepts (slippage guard)
+        coupon: Option<Bytes>,  // Discount code issued by the asset owner
+        scopes: Vec<Symbol>  // Capabilities to lease (empty: all of the asset's capabilities)
     ) -> u64 {
         // Verify the caller is the lessee
         lessee.require_auth();
@@ -259,37 +720,50 @@ impl IoTMarketplace {
             panic!("Asset is not available for lease");
         }
         
+        // Verify the asset's pricing token is still accepted
+        if !Self::get_allowed_tokens(env.clone()).contains(&asset.token) {
+            log!(&env, "Asset pricing token is no longer accepted");
+            panic!("Asset pricing token is no longer accepted");
+        }
+        
+        // Verify the owner's access lists permit the lessee
+        Self::require_permitted(&env, &asset, &lessee);
+        
         // Get current timestamp
         let time = env.ledger().timestamp();
         let end_time = time + duration;
         
-        // Calculate total cost based on payment model and duration
-        let total_cost = match asset.payment_model {
-            PaymentModel::Hourly => asset.price * (duration / 3600),
-            PaymentModel::Daily => asset.price * (duration / 86400),
-            PaymentModel::Weekly => asset.price * (duration / 604800),
-            PaymentModel::Monthly => asset.price * (duration / 2592000),
-            PaymentModel::PayPerUse => asset.price
-        };
-        
         // Get current lease counter
         let mut lease_counter: u64 = env.storage().instance().get(&LEASE_COUNTER).unwrap_or(0);
         lease_counter += 1;
         
+        // Calculate total cost in the pricing token, less any coupon discount
+        let scopes = Self::resolve_scopes(&env, &asset, scopes);
+        let mut total_cost = Self::lease_cost(&env, &asset, duration, &scopes);
+        if let Some(code) = coupon {
+            total_cost -= Self::redeem_coupon(&env, &asset, lease_counter, code, total_cost);
+        }
+        if total_cost > max_payment {
+            log!(&env, "Lease cost exceeds maximum payment");
+            panic!("Lease cost exceeds maximum payment");
+        }
+        
         // Create new lease
-        let lease = Lease {
-            lease_id: lease_counter,
-            asset_id,
-            lessor: asset.owner.clone(),
-            lessee: lessee.clone(),
-            start_time: time,
-            end_time,
-            total_cost,
-            is_active: true,
-            is_paid: false,  // Will be set to true after payment
-            access_key,
-            dispute_raised: false
-        };
+        let mut lease = Self::new_lease(&env, &asset, lease_counter, &lessee, time, end_time, total_cost, asset.deposit, encryption_key, scopes);
+        
+        // Collect the security deposit into escrow
+        if asset.deposit > 0 {
+            token::Client::new(&env, &asset.token).transfer(&lessee, &env.current_contract_address(), &(asset.deposit as i128));
+        }
+        
+        // Requests for assets requiring approval are paid up front and wait for the owner
+        if asset.requires_approval {
+            token::Client::new(&env, &asset.token).transfer(&lessee, &env.current_contract_address(), &(total_cost as i128));
+            lease.is_paid = true;
+            lease.is_active = false;
+            lease.is_pending = true;
+            lease.approval_deadline = time + APPROVAL_WINDOW;
+        }
         
         // Update asset availability
         asset.is_available = false;
@@ -298,11 +772,16 @@ impl IoTMarketplace {
         // Update asset stats
         let mut stats = Self::get_asset_stats(env.clone());
         stats.available -= 1;
-        stats.leased += 1;
+        if !lease.is_pending {
+            stats.leased += 1;
+        }
         env.storage().instance().set(&ALL_ASSETS, &stats);
         
         // Store the lease
         env.storage().instance().set(&LeaseRegistry::Lease(lease_counter), &lease);
+        if !lease.is_pending {
+            Self::index_lease(&env, &lease);
+        }
         
         // Update counter
         env.storage().instance().set(&LEASE_COUNTER, &lease_counter);
@@ -338,8 +817,8 @@ impl IoTMarketplace {
             panic!("Lease is not active or already paid");
         }
         
-        // In real implementation, this would involve token transfer
-        // For now, we'll just mark it as paid
+        // Hold the payment in escrow until the lease is settled
+        token::Client::new(&env, &lease.token).transfer(&payer, &env.current_contract_address(), &(lease.total_cost as i128));
         
         // Update lease as paid
         lease.is_paid = true;
@@ -347,7 +826,8 @@ impl IoTMarketplace {
         
         // Update total revenue
         let mut stats = Self::get_asset_stats(env.clone());
-        stats.total_revenue += lease.total_cost;
+        let revenue = stats.revenue.get(lease.token.clone()).unwrap_or(0);
+        stats.revenue.set(lease.token.clone(), revenue + lease.total_cost);
         env.storage().instance().set(&ALL_ASSETS, &stats);
         
         // Update contract TTL
@@ -381,20 +861,27 @@ impl IoTMarketplace {
             panic!("Lease is not active");
         }
         
-        // Mark lease as inactive
-        lease.is_active = false;
-        env.storage().instance().set(&LeaseRegistry::Lease(lease_id), &lease);
+        // Disputed leases are closed through the dispute process
+        if lease.dispute_raised {
+            log!(&env, "Cannot end a lease under dispute");
+            panic!("Cannot end a lease under dispute");
+        }
         
-        // Update asset availability
-        let mut asset = Self::get_asset(env.clone(), lease.asset_id);
-        asset.is_available = true;
-        env.storage().instance().set(&AssetRegistry::Asset(lease.asset_id), &asset);
+        // Paid multi-signature leases are closed through approve_release
+        if lease.is_paid && env.storage().instance().has(&LeaseRegistry::Release(lease_id)) {
+            log!(&env, "Escrow requires multi-signature release");
+            panic!("Escrow requires multi-signature release");
+        }
         
-        // Update asset stats
-        let mut stats = Self::get_asset_stats(env.clone());
-        stats.available += 1;
-        stats.leased -= 1;
-        env.storage().instance().set(&ALL_ASSETS, &stats);
+        // Release escrow to the lessor, refunding the lessee per the cancellation terms, and free the asset
+        let refund_amount = Self::cancellation_refund(&env, &lease, &caller);
+        let early_termination = caller == lease.lessor && lease.is_paid && env.ledger().timestamp() < lease.end_time;
+        Self::close_lease(&env, &mut lease, refund_amount);
+        
+        // A lessor ending early pays the lessee the termination penalty
+        if early_termination {
+            Self::collect_termination_penalty(&env, &lease);
+        }
         
         // Update contract TTL
         env.storage().instance().extend_ttl(5000, 5000);
@@ -403,233 +890,3162 @@ impl IoTMarketplace {
         return true;
     }
     
-    // Submit a review for an asset
-    pub fn submit_review(
-        env: Env,
-        asset_id: u64,
-        reviewer: Address,
-        rating: u64,
-        comment: String
-    ) -> u64 {
-        // Verify the caller is the reviewer
-        reviewer.require_auth();
+    // Require owner approval for new lease requests on an asset (owner only)
+    pub fn set_asset_requires_approval(env: Env, asset_id: u64, owner: Address, requires_approval: bool) -> bool {
+        // Verify the caller is the owner
+        owner.require_auth();
         
-        // Verify rating is valid (0-100)
-        if rating > 100 {
-            log!(&env, "Rating must be between 0 and 100");
-            panic!("Rating must be between 0 and 100");
+        let mut asset = Self::get_asset(env.clone(), asset_id);
+        if asset.owner != owner {
+            log!(&env, "Only the owner can update the asset");
+            panic!("Only the owner can update the asset");
         }
         
-        // Get current timestamp
-        let time = env.ledger().timestamp();
+        asset.requires_approval = requires_approval;
+        env.storage().instance().set(&AssetRegistry::Asset(asset_id), &asset);
         
-        // Get current review counter
-        let mut review_counter: u64 = env.storage().instance().get(&REVIEW_COUNTER).unwrap_or(0);
-        review_counter += 1;
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
         
-        // Create new review
-        let review = Review {
-            review_id: review_counter,
-            asset_id,
-            reviewer: reviewer.clone(),
-            rating,
-            comment,
-            review_time: time
-        };
+        log!(&env, "Approval requirement updated for asset: {}", asset_id);
+        return true;
+    }
+    
+    // Approve a pending lease request (owner only); the lease runs for its full duration from now
+    pub fn approve_lease(env: Env, lease_id: u64, owner: Address) -> bool {
+        // Verify the caller is the owner
+        owner.require_auth();
         
-        // Store the review
-        env.storage().instance().set(&ReviewRegistry::Review(review_counter), &review);
+        let mut lease = Self::get_lease(env.clone(), lease_id);
+        if lease.lessor != owner {
+            log!(&env, "Only the owner can approve the lease");
+            panic!("Only the owner can approve the lease");
+        }
+        let time = env.ledger().timestamp();
+        if !lease.is_pending || time >= lease.approval_deadline {
+            log!(&env, "Lease request is not pending");
+            panic!("Lease request is not pending");
+        }
         
-        // Update counter
-        env.storage().instance().set(&REVIEW_COUNTER, &review_counter);
+        lease.end_time = time + (lease.end_time - lease.start_time);
+        lease.start_time = time;
+        lease.is_pending = false;
+        lease.is_active = true;
+        env.storage().instance().set(&LeaseRegistry::Lease(lease_id), &lease);
+        Self::index_lease(&env, &lease);
         
-        // Update asset rating (average of all ratings)
-        // In a real implementation, you would calculate the average of all reviews
-        // Here, we'll just update directly
-        let mut asset = Self::get_asset(env.clone(), asset_id);
-        asset.rating = rating;  // Simplified - should be average of all ratings
-        env.storage().instance().set(&AssetRegistry::Asset(asset_id), &asset);
+        // Update asset stats and total revenue
+        let mut stats = Self::get_asset_stats(env.clone());
+        stats.leased += 1;
+        let revenue = stats.revenue.get(lease.token.clone()).unwrap_or(0);
+        stats.revenue.set(lease.token.clone(), revenue + lease.total_cost);
+        env.storage().instance().set(&ALL_ASSETS, &stats);
         
         // Update contract TTL
         env.storage().instance().extend_ttl(5000, 5000);
         
-        log!(&env, "Review submitted: {}", review_counter);
-        return review_counter;
+        log!(&env, "Lease approved: {}", lease_id);
+        return true;
     }
     
-    // Raise a dispute for a lease
-    pub fn raise_dispute(
-        env: Env,
-        lease_id: u64,
-        caller: Address
-    ) -> bool {
-        // Verify the caller is either the lessor or lessee
+    // Decline a pending lease request, refunding the lessee in full
+    // The owner can decline at any time; anyone can do so once the approval window has passed
+    pub fn decline_lease(env: Env, lease_id: u64, caller: Address) -> bool {
+        // Verify the caller
         caller.require_auth();
         
-        // Get the lease
         let mut lease = Self::get_lease(env.clone(), lease_id);
-        
-        // Verify caller is either lessor or lessee
-        if lease.lessor != caller && lease.lessee != caller {
-            log!(&env, "Only the lessor or lessee can raise a dispute");
-            panic!("Only the lessor or lessee can raise a dispute");
+        if !lease.is_pending {
+            log!(&env, "Lease request is not pending");
+            panic!("Lease request is not pending");
         }
-        
-        // Verify lease is active
-        if !lease.is_active {
-            log!(&env, "Cannot raise dispute on inactive lease");
-            panic!("Cannot raise dispute on inactive lease");
+        let time = env.ledger().timestamp();
+        if caller != lease.lessor && time < lease.approval_deadline {
+            log!(&env, "Only the owner can decline before the approval deadline");
+            panic!("Only the owner can decline before the approval deadline");
         }
         
-        // Mark dispute as raised
-        lease.dispute_raised = true;
+        Self::credit(&env, &lease.lessee, &lease.token, (lease.total_cost + lease.deposit) as i128);
+        lease.is_pending = false;
+        lease.deposit_settled = true;
+        lease.closed_time = time;
         env.storage().instance().set(&LeaseRegistry::Lease(lease_id), &lease);
         
+        // Release the asset
+        let mut asset = Self::get_asset(env.clone(), lease.asset_id);
+        asset.is_available = true;
+        env.storage().instance().set(&AssetRegistry::Asset(lease.asset_id), &asset);
+        let mut stats = Self::get_asset_stats(env.clone());
+        stats.available += 1;
+        env.storage().instance().set(&ALL_ASSETS, &stats);
+        
         // Update contract TTL
         env.storage().instance().extend_ttl(5000, 5000);
         
-        log!(&env, "Dispute raised for lease: {}", lease_id);
+        log!(&env, "Lease declined: {}", lease_id);
         return true;
     }
     
-    // Resolve a dispute for a lease
-    pub fn resolve_dispute(
-        env: Env,
-        lease_id: u64,
-        admin: Address,
-        refund_percentage: u64
-    ) -> bool {
-        // Verify the caller is an admin (in a real implementation, this would be a multi-sig or DAO)
-        admin.require_auth();
+    // Add or remove a lessee on the allowlist of one asset or, with owner_wide, all of the owner's assets
+    pub fn allow_lessee(env: Env, owner: Address, owner_wide: bool, asset_id: u64, lessee: Address, allowed: bool) -> bool {
+        let key = if owner_wide {
+            AccessListRegistry::OwnerAllowed(owner.clone(), lessee)
+        } else {
+            AccessListRegistry::AssetAllowed(asset_id, lessee)
+        };
+        Self::set_access_flag(&env, &owner, owner_wide, asset_id, key, allowed);
         
-        // Verify refund percentage is valid (0-100)
-        if refund_percentage > 100 {
-            log!(&env, "Refund percentage must be between 0 and 100");
-            panic!("Refund percentage must be between 0 and 100");
-        }
+        log!(&env, "Allowlist updated");
+        return true;
+    }
+    
+    // Add or remove a lessee on the blocklist of one asset or, with owner_wide, all of the owner's assets
+    pub fn block_lessee(env: Env, owner: Address, owner_wide: bool, asset_id: u64, lessee: Address, blocked: bool) -> bool {
+        let key = if owner_wide {
+            AccessListRegistry::OwnerBlocked(owner.clone(), lessee)
+        } else {
+            AccessListRegistry::AssetBlocked(asset_id, lessee)
+        };
+        Self::set_access_flag(&env, &owner, owner_wide, asset_id, key, blocked);
         
-        // Get the lease
-        let mut lease = Self::get_lease(env.clone(), lease_id);
+        log!(&env, "Blocklist updated");
+        return true;
+    }
+    
+    // Restrict leasing of one asset or, with owner_wide, all of the owner's assets to allowlisted lessees
+    pub fn set_allowlist_only(env: Env, owner: Address, owner_wide: bool, asset_id: u64, enabled: bool) -> bool {
+        let key = if owner_wide {
+            AccessListRegistry::OwnerAllowlistOnly(owner.clone())
+        } else {
+            AccessListRegistry::AssetAllowlistOnly(asset_id)
+        };
+        Self::set_access_flag(&env, &owner, owner_wide, asset_id, key, enabled);
         
-        // Verify dispute was raised
-        if !lease.dispute_raised {
-            log!(&env, "No dispute raised for this lease");
-            panic!("No dispute raised for this lease");
+        log!(&env, "Allowlist-only mode updated");
+        return true;
+    }
+    
+    // Check whether an asset's access lists permit a lessee
+    pub fn is_lessee_permitted(env: Env, asset_id: u64, lessee: Address) -> bool {
+        let asset = Self::get_asset(env.clone(), asset_id);
+        return Self::lessee_permitted(&env, &asset, &lessee);
+    }
+    
+    // Allow lessees of an asset to sublease it, paying the owner a share of the proceeds (owner only)
+    pub fn set_asset_sublease(env: Env, asset_id: u64, owner: Address, allows_sublease: bool, sublease_share: u64) -> bool {
+        // Verify the caller is the owner
+        owner.require_auth();
+        
+        let mut asset = Self::get_asset(env.clone(), asset_id);
+        if asset.owner != owner {
+            log!(&env, "Only the owner can update the asset");
+            panic!("Only the owner can update the asset");
+        }
+        if sublease_share > 100 {
+            log!(&env, "Sublease share must be a percentage");
+            panic!("Sublease share must be a percentage");
         }
         
-        // Calculate refund amount
-        let refund_amount = (lease.total_cost * refund_percentage) / 100;
+        asset.allows_sublease = allows_sublease;
+        asset.sublease_share = sublease_share;
+        env.storage().instance().set(&AssetRegistry::Asset(asset_id), &asset);
         
-        // In real implementation, this would involve token transfers
-        // For refunds to the lessee and remaining payment to the lessor
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
         
-        // Mark dispute as resolved and lease as inactive
-        lease.dispute_raised = false;
-        lease.is_active = false;
-        env.storage().instance().set(&LeaseRegistry::Lease(lease_id), &lease);
+        log!(&env, "Sublease settings updated for asset: {}", asset_id);
+        return true;
+    }
+    
+    // Resell part of an active lease to a sublessee, who pays the price into escrow
+    // The sublease ends within the parent lease and is terminated if the parent ends or is disputed
+    pub fn sublease(env: Env, lease_id: u64, lessee: Address, sublessee: Address, duration: u64, price: u64) -> u64 {
+        // Verify both the reselling lessee and the sublessee
+        lessee.require_auth();
+        sublessee.require_auth();
         
-        // Update asset availability
-        let mut asset = Self::get_asset(env.clone(), lease.asset_id);
-        asset.is_available = true;
-        env.storage().instance().set(&AssetRegistry::Asset(lease.asset_id), &asset);
+        let parent = Self::get_lease(env.clone(), lease_id);
+        if parent.lessee != lessee {
+            log!(&env, "Only the lessee can sublease");
+            panic!("Only the lessee can sublease");
+        }
+        let asset = Self::get_asset(env.clone(), parent.asset_id);
+        if !asset.allows_sublease || parent.parent_lease_id != 0 {
+            log!(&env, "Subleasing is not allowed for this lease");
+            panic!("Subleasing is not allowed for this lease");
+        }
         
-        // Update asset stats
+        // The parent must currently grant access and outlast the sublease
+        let time = env.ledger().timestamp();
+        if !parent.is_active || !parent.is_paid || parent.dispute_raised || time < parent.start_time
+            || duration == 0 || time + duration > parent.end_time {
+            log!(&env, "Sublease must fit within an active, paid lease");
+            panic!("Sublease must fit within an active, paid lease");
+        }
+        if sublessee == lessee || sublessee == asset.owner {
+            log!(&env, "Invalid sublessee");
+            panic!("Invalid sublessee");
+        }
+        
+        // Access is indexed by (asset, lessee), so a sublessee can hold only one live lease of the asset
+        if env.storage().instance().has(&LeaseRegistry::Active(parent.asset_id, sublessee.clone())) {
+            log!(&env, "Sublessee already holds a lease of this asset");
+            panic!("Sublessee already holds a lease of this asset");
+        }
+        Self::require_permitted(&env, &asset, &sublessee);
+        
+        token::Client::new(&env, &parent.token).transfer(&sublessee, &env.current_contract_address(), &(price as i128));
+        
+        // Create the sublease, paid up front, with the reselling lessee as lessor
+        let sublease_id: u64 = env.storage().instance().get(&LEASE_COUNTER).unwrap_or(0) + 1;
+        let mut lease = Self::new_lease(
+            &env,
+            &asset,
+            sublease_id,
+            &sublessee,
+            time,
+            time + duration,
+            price,
+            0,
+            BytesN::from_array(&env, &[0; 32]),
+            parent.scopes.clone()
+        );
+        lease.lessor = lessee;
+        lease.token = parent.token.clone();
+        lease.is_paid = true;
+        lease.is_streaming = false;
+        lease.cancellation_policy = CancellationPolicy::Flexible;
+        lease.termination_penalty = 0;
+        lease.parent_lease_id = lease_id;
+        lease.owner_share = asset.sublease_share;
+        env.storage().instance().set(&LeaseRegistry::Lease(sublease_id), &lease);
+        env.storage().instance().set(&LEASE_COUNTER, &sublease_id);
+        Self::index_lease(&env, &lease);
+        
+        let mut subleases = Self::get_subleases(env.clone(), lease_id);
+        subleases.push_back(sublease_id);
+        env.storage().instance().set(&LeaseRegistry::Subleases(lease_id), &subleases);
+        
+        // Update total revenue
         let mut stats = Self::get_asset_stats(env.clone());
-        stats.available += 1;
-        stats.leased -= 1;
-        // Adjust revenue based on refund
-        stats.total_revenue -= refund_amount;
+        let revenue = stats.revenue.get(parent.token.clone()).unwrap_or(0);
+        stats.revenue.set(parent.token.clone(), revenue + price);
         env.storage().instance().set(&ALL_ASSETS, &stats);
         
         // Update contract TTL
         env.storage().instance().extend_ttl(5000, 5000);
         
-        log!(&env, "Dispute resolved for lease: {}", lease_id);
-        return true;
+        log!(&env, "Sublease created with ID: {}", sublease_id);
+        return sublease_id;
     }
     
-    // Get assets by owner
-    pub fn get_assets_by_owner(env: Env, owner: Address) -> Vec<Asset> {
-        let asset_counter: u64 = env.storage().instance().get(&ASSET_COUNTER).unwrap_or(0);
-        let mut assets = Vec::new(&env);
+    // List an asset for an English auction of a future lease window (owner only)
+    pub fn start_auction(
+        env: Env,
+        asset_id: u64,
+        owner: Address,
+        lease_start: u64,
+        duration: u64,
+        reserve_price: u64,
+        end_time: u64
+    ) -> u64 {
+        // Verify the caller is the owner
+        owner.require_auth();
         
-        // Iterate through all assets and find those owned by the specified owner
-        for i in 1..=asset_counter {
-            let asset = Self::get_asset(env.clone(), i);
-            if asset.owner == owner {
-                assets.push_back(asset);
-            }
+        let mut asset = Self::get_asset(env.clone(), asset_id);
+        if asset.owner != owner {
+            log!(&env, "Only the owner can auction the asset");
+            panic!("Only the owner can auction the asset");
+        }
+        if !asset.is_available {
+            log!(&env, "Asset is not available for lease");
+            panic!("Asset is not available for lease");
+        }
+        if !Self::get_allowed_tokens(env.clone()).contains(&asset.token) {
+            log!(&env, "Asset pricing token is no longer accepted");
+            panic!("Asset pricing token is no longer accepted");
         }
         
-        return assets;
+        // Bidding must close before the auctioned lease window opens
+        if end_time <= env.ledger().timestamp() || lease_start < end_time || duration == 0 {
+            log!(&env, "Invalid auction window");
+            panic!("Invalid auction window");
+        }
+        
+        let auction_id: u64 = env.storage().instance().get(&AUCTION_COUNTER).unwrap_or(0) + 1;
+        let auction = Auction {
+            auction_id,
+            asset_id,
+            seller: owner.clone(),
+            token: asset.token.clone(),
+            lease_start,
+            duration,
+            reserve_price,
+            deposit: asset.deposit,
+            end_time,
+            has_bid: false,
+            highest_bidder: owner,  // Placeholder until the first bid
+            highest_bid: 0,
+            is_settled: false,
+            lease_id: 0
+        };
+        
+        // Reserve the asset for the auctioned window
+        asset.is_available = false;
+        env.storage().instance().set(&AssetRegistry::Asset(asset_id), &asset);
+        let mut stats = Self::get_asset_stats(env.clone());
+        stats.available -= 1;
+        env.storage().instance().set(&ALL_ASSETS, &stats);
+        
+        env.storage().instance().set(&AuctionRegistry::Auction(auction_id), &auction);
+        env.storage().instance().set(&AUCTION_COUNTER, &auction_id);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Auction started with ID: {}", auction_id);
+        return auction_id;
     }
     
-    // Get active leases by lessee
-    pub fn get_leases_by_lessee(env: Env, lessee: Address) -> Vec<Lease> {
-        let lease_counter: u64 = env.storage().instance().get(&LEASE_COUNTER).unwrap_or(0);
-        let mut leases = Vec::new(&env);
+    // Place a bid, escrowing the amount and deposit; the previous highest bidder is refunded
+    pub fn bid(env: Env, auction_id: u64, bidder: Address, amount: u64) -> bool {
+        // Verify the caller is the bidder
+        bidder.require_auth();
+        
+        let mut auction = Self::get_auction(env.clone(), auction_id);
+        if env.ledger().timestamp() >= auction.end_time {
+            log!(&env, "Auction has ended");
+            panic!("Auction has ended");
+        }
+        if bidder == auction.seller {
+            log!(&env, "Seller cannot bid");
+            panic!("Seller cannot bid");
+        }
+        Self::require_permitted(&env, &Self::get_asset(env.clone(), auction.asset_id), &bidder);
+        if amount < auction.reserve_price || (auction.has_bid && amount <= auction.highest_bid) {
+            log!(&env, "Bid too low");
+            panic!("Bid too low");
+        }
         
-        // Iterate through all leases and find active ones for the specified lessee
-        for i in 1..=lease_counter {
-            let lease = Self::get_lease(env.clone(), i);
-            if lease.lessee == lessee && lease.is_active {
-                leases.push_back(lease);
-            }
+        // Escrow the new bid before refunding the outbid bidder
+        let escrow = (amount + auction.deposit) as i128;
+        token::Client::new(&env, &auction.token).transfer(&bidder, &env.current_contract_address(), &escrow);
+        if auction.has_bid {
+            Self::credit(&env, &auction.highest_bidder, &auction.token, (auction.highest_bid + auction.deposit) as i128);
         }
         
-        return leases;
+        auction.has_bid = true;
+        auction.highest_bidder = bidder;
+        auction.highest_bid = amount;
+        env.storage().instance().set(&AuctionRegistry::Auction(auction_id), &auction);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Bid placed on auction: {}", auction_id);
+        return true;
     }
     
-    // Get available assets by type
-    pub fn get_available_assets_by_type(env: Env, asset_type: String) -> Vec<Asset> {
-        let asset_counter: u64 = env.storage().instance().get(&ASSET_COUNTER).unwrap_or(0);
-        let mut assets = Vec::new(&env);
+    // Close an ended auction (callable by anyone), creating a paid lease for the winner
+    pub fn settle_auction(env: Env, auction_id: u64) -> u64 {
+        let mut auction = Self::get_auction(env.clone(), auction_id);
+        if env.ledger().timestamp() < auction.end_time || auction.is_settled {
+            log!(&env, "Auction cannot be settled");
+            panic!("Auction cannot be settled");
+        }
         
-        // Iterate through all assets and find available ones of the specified type
-        for i in 1..=asset_counter {
-            let asset = Self::get_asset(env.clone(), i);
-            if asset.is_available && asset.asset_type == asset_type {
-                assets.push_back(asset);
-            }
+        let mut asset = Self::get_asset(env.clone(), auction.asset_id);
+        let mut stats = Self::get_asset_stats(env.clone());
+        
+        if auction.has_bid {
+            // The winning bid is already escrowed, so the lease starts out paid
+            let lease_id: u64 = env.storage().instance().get(&LEASE_COUNTER).unwrap_or(0) + 1;
+            let mut lease = Self::new_lease(
+                &env,
+                &asset,
+                lease_id,
+                &auction.highest_bidder,
+                auction.lease_start,
+                auction.lease_start + auction.duration,
+                auction.highest_bid,
+                auction.deposit,
+                BytesN::from_array(&env, &[0; 32]),
+                Self::resolve_scopes(&env, &asset, Vec::new(&env))
+            );
+            lease.token = auction.token.clone();
+            lease.is_paid = true;
+            env.storage().instance().set(&LeaseRegistry::Lease(lease_id), &lease);
+            Self::index_lease(&env, &lease);
+            env.storage().instance().set(&LEASE_COUNTER, &lease_id);
+            
+            stats.leased += 1;
+            let revenue = stats.revenue.get(auction.token.clone()).unwrap_or(0);
+            stats.revenue.set(auction.token.clone(), revenue + auction.highest_bid);
+            auction.lease_id = lease_id;
+        } else {
+            // No bids: release the reservation
+            asset.is_available = true;
+            env.storage().instance().set(&AssetRegistry::Asset(auction.asset_id), &asset);
+            stats.available += 1;
         }
+        env.storage().instance().set(&ALL_ASSETS, &stats);
         
-        return assets;
+        auction.is_settled = true;
+        env.storage().instance().set(&AuctionRegistry::Auction(auction_id), &auction);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Auction settled: {}", auction_id);
+        return auction.lease_id;
     }
     
-    // Helper function to get asset stats
-    pub fn get_asset_stats(env: Env) -> AssetStats {
-        env.storage().instance().get(&ALL_ASSETS).unwrap_or(AssetStats {
-            available: 0,
-            leased: 0,
-            registered: 0,
-            total_revenue: 0
-        })
+    // Offer to lease an asset on custom terms, escrowing the offered price and the deposit
+    pub fn make_offer(
+        env: Env,
+        asset_id: u64,
+        lessee: Address,
+        price: u64,
+        start: u64,
+        duration: u64,
+        expiry: u64
+    ) -> u64 {
+        // Verify the caller is the lessee
+        lessee.require_auth();
+        
+        let asset = Self::get_asset(env.clone(), asset_id);
+        if lessee == asset.owner {
+            log!(&env, "Owner cannot make an offer");
+            panic!("Owner cannot make an offer");
+        }
+        if !Self::get_allowed_tokens(env.clone()).contains(&asset.token) {
+            log!(&env, "Asset pricing token is no longer accepted");
+            panic!("Asset pricing token is no longer accepted");
+        }
+        Self::require_permitted(&env, &asset, &lessee);
+        Self::check_offer_terms(&env, start, duration, expiry);
+        
+        let escrowed = price + asset.deposit;
+        token::Client::new(&env, &asset.token).transfer(&lessee, &env.current_contract_address(), &(escrowed as i128));
+        
+        let offer_id: u64 = env.storage().instance().get(&OFFER_COUNTER).unwrap_or(0) + 1;
+        let offer = Offer {
+            offer_id,
+            asset_id,
+            lessee,
+            owner: asset.owner.clone(),
+            token: asset.token.clone(),
+            price,
+            start,
+            duration,
+            expiry,
+            deposit: asset.deposit,
+            escrowed,
+            status: OfferStatus::Pending,
+            lease_id: 0
+        };
+        env.storage().instance().set(&OfferRegistry::Offer(offer_id), &offer);
+        env.storage().instance().set(&OFFER_COUNTER, &offer_id);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Offer made with ID: {}", offer_id);
+        return offer_id;
     }
     
-    // Helper function to get an asset by ID
-    pub fn get_asset(env: Env, asset_id: u64) -> Asset {
-        let key = AssetRegistry::Asset(asset_id);
-        env.storage().instance().get(&key).unwrap_or_else(|| {
-            log!(&env, "Asset not found: {}", asset_id);
-            panic!("Asset not found");
-        })
+    // Propose new terms for a pending offer (owner only)
+    pub fn counter_offer(
+        env: Env,
+        offer_id: u64,
+        owner: Address,
+        price: u64,
+        start: u64,
+        duration: u64,
+        expiry: u64
+    ) -> bool {
+        // Verify the caller is the owner
+        owner.require_auth();
+        
+        let mut offer = Self::get_offer(env.clone(), offer_id);
+        if offer.owner != owner {
+            log!(&env, "Only the owner can counter the offer");
+            panic!("Only the owner can counter the offer");
+        }
+        if offer.status != OfferStatus::Pending || env.ledger().timestamp() >= offer.expiry {
+            log!(&env, "Offer is not open");
+            panic!("Offer is not open");
+        }
+        Self::check_offer_terms(&env, start, duration, expiry);
+        
+        offer.price = price;
+        offer.start = start;
+        offer.duration = duration;
+        offer.expiry = expiry;
+        offer.status = OfferStatus::Countered;
+        env.storage().instance().set(&OfferRegistry::Offer(offer_id), &offer);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Offer countered: {}", offer_id);
+        return true;
     }
     
-    // Helper function to get a lease by ID
-    pub fn get_lease(env: Env, lease_id: u64) -> Lease {
-        let key = LeaseRegistry::Lease(lease_id);
-        env.storage().instance().get(&key).unwrap_or_else(|| {
-            log!(&env, "Lease not found: {}", lease_id);
-            panic!("Lease not found");
-        })
+    // Accept the current terms of an offer, creating a paid lease
+    // The owner accepts pending offers; the lessee accepts counter-offers and settles the price difference
+    pub fn accept_offer(env: Env, offer_id: u64, caller: Address) -> u64 {
+        // Verify the caller
+        caller.require_auth();
+        
+        let mut offer = Self::get_offer(env.clone(), offer_id);
+        let responder = if offer.status == OfferStatus::Countered { &offer.lessee } else { &offer.owner };
+        if caller != *responder {
+            log!(&env, "Caller cannot accept the offer");
+            panic!("Caller cannot accept the offer");
+        }
+        if (offer.status != OfferStatus::Pending && offer.status != OfferStatus::Countered)
+            || env.ledger().timestamp() >= offer.expiry {
+            log!(&env, "Offer is not open");
+            panic!("Offer is not open");
+        }
+        
+        let mut asset = Self::get_asset(env.clone(), offer.asset_id);
+        if !asset.is_available {
+            log!(&env, "Asset is not available for lease");
+            panic!("Asset is not available for lease");
+        }
+        Self::require_permitted(&env, &asset, &offer.lessee);
+        
+        // Settle any difference between the escrow and the agreed terms
+        let required = offer.price + offer.deposit;
+        if required > offer.escrowed {
+            token::Client::new(&env, &offer.token).transfer(&offer.lessee, &env.current_contract_address(), &((required - offer.escrowed) as i128));
+        } else {
+            Self::credit(&env, &offer.lessee, &offer.token, (offer.escrowed - required) as i128);
+        }
+        offer.escrowed = required;
+        
+        // The agreed price is already escrowed, so the lease starts out paid
+        let lease_id: u64 = env.storage().instance().get(&LEASE_COUNTER).unwrap_or(0) + 1;
+        let mut lease = Self::new_lease(
+            &env,
+            &asset,
+            lease_id,
+            &offer.lessee,
+            offer.start,
+            offer.start + offer.duration,
+            offer.price,
+            offer.deposit,
+            BytesN::from_array(&env, &[0; 32]),
+            Self::resolve_scopes(&env, &asset, Vec::new(&env))
+        );
+        lease.token = offer.token.clone();
+        lease.is_paid = true;
+        env.storage().instance().set(&LeaseRegistry::Lease(lease_id), &lease);
+        Self::index_lease(&env, &lease);
+        env.storage().instance().set(&LEASE_COUNTER, &lease_id);
+        
+        // Update asset availability and stats
+        asset.is_available = false;
+        env.storage().instance().set(&AssetRegistry::Asset(offer.asset_id), &asset);
+        let mut stats = Self::get_asset_stats(env.clone());
+        stats.available -= 1;
+        stats.leased += 1;
+        let revenue = stats.revenue.get(offer.token.clone()).unwrap_or(0);
+        stats.revenue.set(offer.token.clone(), revenue + offer.price);
+        env.storage().instance().set(&ALL_ASSETS, &stats);
+        
+        offer.status = OfferStatus::Accepted;
+        offer.lease_id = lease_id;
+        env.storage().instance().set(&OfferRegistry::Offer(offer_id), &offer);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Offer accepted: {}", offer_id);
+        return lease_id;
+    }
+    
+    // Reject an open offer (owner) or withdraw it (lessee), refunding the escrow to the lessee
+    pub fn reject_offer(env: Env, offer_id: u64, caller: Address) -> bool {
+        // Verify the caller
+        caller.require_auth();
+        
+        let mut offer = Self::get_offer(env.clone(), offer_id);
+        if caller != offer.owner && caller != offer.lessee {
+            log!(&env, "Caller is not a party to the offer");
+            panic!("Caller is not a party to the offer");
+        }
+        if offer.status != OfferStatus::Pending && offer.status != OfferStatus::Countered {
+            log!(&env, "Offer is not open");
+            panic!("Offer is not open");
+        }
+        
+        Self::credit(&env, &offer.lessee, &offer.token, offer.escrowed as i128);
+        offer.escrowed = 0;
+        offer.status = OfferStatus::Rejected;
+        env.storage().instance().set(&OfferRegistry::Offer(offer_id), &offer);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Offer rejected: {}", offer_id);
+        return true;
+    }
+    
+    // Set the X25519 public key the lessor should encrypt access keys to (lessee only)
+    pub fn set_encryption_key(env: Env, lease_id: u64, lessee: Address, encryption_key: BytesN<32>) -> bool {
+        // Verify the caller is the lessee
+        lessee.require_auth();
+        
+        let mut lease = Self::get_lease(env.clone(), lease_id);
+        if lease.lessee != lessee {
+            log!(&env, "Only the lessee can set the encryption key");
+            panic!("Only the lessee can set the encryption key");
+        }
+        if encryption_key == BytesN::from_array(&env, &[0; 32]) {
+            log!(&env, "Invalid encryption key");
+            panic!("Invalid encryption key");
+        }
+        
+        lease.encryption_key = encryption_key;
+        env.storage().instance().set(&LeaseRegistry::Lease(lease_id), &lease);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Encryption key set for lease: {}", lease_id);
+        return true;
+    }
+    
+    // Deliver the first access key, encrypted to the lessee's public key (lessor only)
+    pub fn deliver_access_key(env: Env, lease_id: u64, lessor: Address, ciphertext: Bytes) -> u32 {
+        let lease = Self::get_lease(env.clone(), lease_id);
+        if lease.key_version != 0 {
+            log!(&env, "Access key already delivered");
+            panic!("Access key already delivered");
+        }
+        return Self::store_access_key(&env, lease, &lessor, ciphertext);
+    }
+    
+    // Replace a delivered access key with a new one, encrypted to the lessee's public key (lessor only)
+    pub fn rotate_access_key(env: Env, lease_id: u64, lessor: Address, ciphertext: Bytes) -> u32 {
+        let lease = Self::get_lease(env.clone(), lease_id);
+        if lease.key_version == 0 {
+            log!(&env, "No access key to rotate");
+            panic!("No access key to rotate");
+        }
+        return Self::store_access_key(&env, lease, &lessor, ciphertext);
+    }
+    
+    // Revoke the lessee's access key; no further keys can be delivered for the lease (lessor only)
+    pub fn revoke_access(env: Env, lease_id: u64, lessor: Address) -> bool {
+        // Verify the caller is the lessor
+        lessor.require_auth();
+        
+        let mut lease = Self::get_lease(env.clone(), lease_id);
+        if lease.lessor != lessor {
+            log!(&env, "Only the lessor can revoke access");
+            panic!("Only the lessor can revoke access");
+        }
+        
+        lease.access_revoked = true;
+        env.storage().instance().set(&LeaseRegistry::Lease(lease_id), &lease);
+        env.storage().instance().remove(&LeaseRegistry::AccessKey(lease_id));
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Access revoked for lease: {}", lease_id);
+        return true;
+    }
+    
+    // Check whether a lessee has access to an asset at a given time (for data gateways)
+    // Access requires a paid, active lease whose window covers at_time and that is not revoked or suspended by a dispute
+    pub fn verify_access(env: Env, asset_id: u64, lessee: Address, at_time: u64) -> Option<AccessGrant> {
+        let lease_id: u64 = env.storage().instance().get(&LeaseRegistry::Active(asset_id, lessee))?;
+        let lease = Self::get_lease(env.clone(), lease_id);
+        
+        // Subscriptions keep access through the renewal grace period
+        let expires_at = if lease.auto_renew {
+            lease.end_time + SUBSCRIPTION_GRACE_PERIOD
+        } else {
+            lease.end_time
+        };
+        
+        if !lease.is_active || !lease.is_paid || lease.access_revoked || lease.dispute_raised
+            || at_time < lease.start_time || at_time >= expires_at {
+            return None;
+        }
+        
+        return Some(AccessGrant {
+            lease_id,
+            scopes: lease.scopes,
+            expires_at
+        });
+    }
+    
+    // Register the ed25519 public key the lessee signs off-chain access tokens with (lessee only)
+    pub fn set_signing_key(env: Env, lease_id: u64, lessee: Address, signing_key: BytesN<32>) -> bool {
+        // Verify the caller is the lessee
+        lessee.require_auth();
+        
+        let mut lease = Self::get_lease(env.clone(), lease_id);
+        if lease.lessee != lessee {
+            log!(&env, "Only the lessee can set the signing key");
+            panic!("Only the lessee can set the signing key");
+        }
+        
+        lease.signing_key = signing_key;
+        env.storage().instance().set(&LeaseRegistry::Lease(lease_id), &lease);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Signing key set for lease: {}", lease_id);
+        return true;
+    }
+    
+    // Revoke an off-chain access token (lessee or lessor); the revocation is kept until the token itself expires
+    pub fn revoke_access_token(env: Env, token: AccessToken, caller: Address) -> bool {
+        // Verify the caller
+        caller.require_auth();
+        
+        let lease = Self::get_lease(env.clone(), token.lease_id);
+        if caller != lease.lessee && caller != lease.lessor {
+            log!(&env, "Caller is not a party to the lease");
+            panic!("Caller is not a party to the lease");
+        }
+        
+        // Revocations are unbounded, so they live in persistent storage rather than the contract instance,
+        // for as long as the revoked token could still be presented
+        let key = LeaseRegistry::RevokedToken(token.lease_id, token.nonce);
+        let ledgers = (token.expiry.saturating_sub(env.ledger().timestamp()) / LEDGER_CLOSE_TIME + 1).min(u32::MAX as u64) as u32;
+        env.storage().persistent().set(&key, &true);
+        env.storage().persistent().extend_ttl(&key, ledgers, ledgers);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Access token {} revoked for lease: {}", token.nonce, token.lease_id);
+        return true;
+    }
+    
+    // Get the message a lessee signs for an access token: this contract's address followed by the token
+    pub fn access_token_payload(env: Env, token: AccessToken) -> Bytes {
+        let mut payload = env.current_contract_address().to_xdr(&env);
+        payload.append(&token.to_xdr(&env));
+        return payload;
+    }
+    
+    // Validate a signed access token (for gateways, via simulation), returning the access it grants
+    // Returns None if the token is expired, revoked or the lease no longer grants access.
+    // A signature that does not verify against the lease's signing key fails the call (the host aborts
+    // on ed25519 verification failure), so gateways must treat a failed simulation as access denied.
+    pub fn validate_access_token(env: Env, token: AccessToken, signature: BytesN<64>) -> Option<AccessGrant> {
+        let lease: Lease = env.storage().instance().get(&LeaseRegistry::Lease(token.lease_id))?;
+        if lease.asset_id != token.asset_id || lease.lessee != token.lessee
+            || lease.signing_key == BytesN::from_array(&env, &[0; 32]) {
+            return None;
+        }
+        if env.ledger().timestamp() >= token.expiry
+            || env.storage().persistent().has(&LeaseRegistry::RevokedToken(token.lease_id, token.nonce)) {
+            return None;
+        }
+        
+        let payload = Self::access_token_payload(env.clone(), token.clone());
+        env.crypto().ed25519_verify(&lease.signing_key, &payload, &signature);
+        
+        let grant = Self::verify_access(env.clone(), token.asset_id, token.lessee, env.ledger().timestamp())?;
+        if grant.lease_id != token.lease_id {
+            return None;
+        }
+        return Some(grant);
+    }
+    
+    // Get the encrypted access key of a lease (lessee only)
+    pub fn get_access_key(env: Env, lease_id: u64, lessee: Address) -> Bytes {
+        // Verify the caller is the lessee
+        lessee.require_auth();
+        
+        let lease = Self::get_lease(env.clone(), lease_id);
+        if lease.lessee != lessee {
+            log!(&env, "Only the lessee can read the access key");
+            panic!("Only the lessee can read the access key");
+        }
+        
+        return env.storage().instance().get(&LeaseRegistry::AccessKey(lease_id)).unwrap_or_else(|| {
+            log!(&env, "Access key not available");
+            panic!("Access key not available");
+        });
+    }
+    
+    // Turn a lease into a subscription renewed each period from the lessee's token allowance
+    pub fn enable_subscription(env: Env, lease_id: u64, lessee: Address) -> bool {
+        // Verify the caller is the lessee
+        lessee.require_auth();
+        
+        let mut lease = Self::get_lease(env.clone(), lease_id);
+        if lease.lessee != lessee {
+            log!(&env, "Only the lessee can subscribe");
+            panic!("Only the lessee can subscribe");
+        }
+        if !lease.is_active {
+            log!(&env, "Lease is not active");
+            panic!("Lease is not active");
+        }
+        
+        // Renewals release escrow to the lessor each period, which multi-signature leases forbid
+        let asset = Self::get_asset(env.clone(), lease.asset_id);
+        if asset.payment_model == PaymentModel::PayPerUse || lease.end_time == lease.start_time
+            || lease.parent_lease_id != 0 || env.storage().instance().has(&LeaseRegistry::Release(lease_id)) {
+            log!(&env, "Lease cannot be renewed automatically");
+            panic!("Lease cannot be renewed automatically");
+        }
+        
+        lease.auto_renew = true;
+        lease.renewal_period = lease.end_time - lease.start_time;
+        env.storage().instance().set(&LeaseRegistry::Lease(lease_id), &lease);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Subscription enabled for lease: {}", lease_id);
+        return true;
+    }
+    
+    // Stop future renewals of a subscription (the current period runs to its end)
+    pub fn cancel_subscription(env: Env, lease_id: u64, lessee: Address) -> bool {
+        // Verify the caller is the lessee
+        lessee.require_auth();
+        
+        let mut lease = Self::get_lease(env.clone(), lease_id);
+        if lease.lessee != lessee {
+            log!(&env, "Only the lessee can cancel the subscription");
+            panic!("Only the lessee can cancel the subscription");
+        }
+        if !lease.auto_renew {
+            log!(&env, "Lease is not a subscription");
+            panic!("Lease is not a subscription");
+        }
+        
+        lease.auto_renew = false;
+        env.storage().instance().set(&LeaseRegistry::Lease(lease_id), &lease);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Subscription cancelled for lease: {}", lease_id);
+        return true;
+    }
+    
+    // Renew subscriptions whose period has ended (callable by anyone)
+    // Charges the next period via transfer_from; subscriptions that cannot pay lapse after the grace period.
+    // Unknown leases, unavailable prices and failed payments are skipped so one bad entry does not revert the batch.
+    pub fn renew_due(env: Env, lease_ids: Vec<u64>) -> u32 {
+        let time = env.ledger().timestamp();
+        let contract = env.current_contract_address();
+        let mut renewed = 0u32;
+        
+        for lease_id in lease_ids.iter() {
+            let mut lease: Lease = match env.storage().instance().get(&LeaseRegistry::Lease(lease_id)) {
+                Some(lease) => lease,
+                None => continue
+            };
+            if !lease.auto_renew || !lease.is_active || !lease.is_paid || lease.dispute_raised || time < lease.end_time {
+                continue;
+            }
+            
+            // Renewals are charged in the lease's token, so a subscription ends once the owner switches tokens
+            let asset = Self::get_asset(env.clone(), lease.asset_id);
+            if asset.token != lease.token {
+                lease.auto_renew = false;
+                Self::close_lease(&env, &mut lease, 0);
+                log!(&env, "Subscription ended after token change for lease: {}", lease_id);
+                continue;
+            }
+            
+            // Charge the next period at the current price; an unavailable price counts as a missed payment
+            let token = token::Client::new(&env, &lease.token);
+            let cost = match Self::try_lease_cost(&env, &asset, lease.renewal_period, &lease.scopes) {
+                Some(cost) if matches!(token.try_transfer_from(&contract, &lease.lessee, &contract, &(cost as i128)), Ok(Ok(()))) => cost,
+                _ => {
+                    if time >= lease.end_time + SUBSCRIPTION_GRACE_PERIOD {
+                        lease.auto_renew = false;
+                        Self::close_lease(&env, &mut lease, 0);
+                        log!(&env, "Subscription lapsed for lease: {}", lease_id);
+                    }
+                    continue;
+                }
+            };
+            
+            // Release the finished period to the lessor and start the next one
+            let escrowed = Self::escrowed(&lease);
+            Self::pay_lessor(&env, &lease, &asset.asset_type, escrowed);
+            lease.start_time = lease.end_time;
+            lease.end_time += lease.renewal_period;
+            lease.total_cost = cost;
+            lease.released = 0;
+            env.storage().instance().set(&LeaseRegistry::Lease(lease_id), &lease);
+            
+            // Update total revenue
+            let mut stats = Self::get_asset_stats(env.clone());
+            let revenue = stats.revenue.get(lease.token.clone()).unwrap_or(0);
+            stats.revenue.set(lease.token.clone(), revenue + cost);
+            env.storage().instance().set(&ALL_ASSETS, &stats);
+            
+            renewed += 1;
+            log!(&env, "Subscription renewed for lease: {}", lease_id);
+        }
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        return renewed;
+    }
+    
+    // Require M-of-3 approvals (lessor, lessee, arbitrator) to release the lease escrow
+    pub fn enable_multisig_release(
+        env: Env,
+        lease_id: u64,
+        lessee: Address,
+        required_approvals: u32
+    ) -> bool {
+        // Verify the caller is the lessee
+        lessee.require_auth();
+        
+        let lease = Self::get_lease(env.clone(), lease_id);
+        if lease.lessee != lessee {
+            log!(&env, "Only the lessee can choose the release mode");
+            panic!("Only the lessee can choose the release mode");
+        }
+        
+        // The release mode is fixed once funds are in escrow
+        if !lease.is_active || lease.is_paid {
+            log!(&env, "Lease is not active or already paid");
+            panic!("Lease is not active or already paid");
+        }
+        if lease.auto_renew {
+            log!(&env, "Subscriptions cannot use multi-signature release");
+            panic!("Subscriptions cannot use multi-signature release");
+        }
+        if !(2..=3).contains(&required_approvals) {
+            log!(&env, "Required approvals must be 2 or 3");
+            panic!("Required approvals must be 2 or 3");
+        }
+        
+        let release = MultiSigRelease {
+            required_approvals,
+            arbitrator: Self::assign_arbitrator(&env, &lease),
+            approvals: Map::new(&env)
+        };
+        env.storage().instance().set(&LeaseRegistry::Release(lease_id), &release);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Multi-signature release enabled for lease: {}", lease_id);
+        return true;
+    }
+    
+    // Approve releasing the escrow with the given refund percentage to the lessee (rest to the lessor)
+    // Escrow is released once enough signers approve the same split
+    pub fn approve_release(
+        env: Env,
+        lease_id: u64,
+        approver: Address,
+        refund_percentage: u64
+    ) -> bool {
+        // Verify the caller is the approver
+        approver.require_auth();
+        
+        let mut lease = Self::get_lease(env.clone(), lease_id);
+        let mut release = Self::get_multisig_release(env.clone(), lease_id);
+        
+        // Verify the approver is one of the signers
+        if approver != lease.lessor && approver != lease.lessee && approver != release.arbitrator {
+            log!(&env, "Only the lessor, lessee or arbitrator can approve a release");
+            panic!("Only the lessor, lessee or arbitrator can approve a release");
+        }
+        if !lease.is_active || !lease.is_paid {
+            log!(&env, "Lease has no escrow to release");
+            panic!("Lease has no escrow to release");
+        }
+        if refund_percentage > 100 {
+            log!(&env, "Refund percentage must be between 0 and 100");
+            panic!("Refund percentage must be between 0 and 100");
+        }
+        
+        // Record the approval (signers may change their mind until release)
+        release.approvals.set(approver, refund_percentage);
+        env.storage().instance().set(&LeaseRegistry::Release(lease_id), &release);
+        
+        // Count signers agreeing on this split
+        let mut matching = 0u32;
+        for (_, approved) in release.approvals.iter() {
+            if approved == refund_percentage {
+                matching += 1;
+            }
+        }
+        
+        if matching >= release.required_approvals {
+            // An amicable settlement also closes any pending dispute
+            if lease.dispute_raised {
+                let mut dispute = Self::get_dispute(env.clone(), lease.dispute_id);
+                if !dispute.jury.is_empty() && !dispute.is_resolved {
+                    Self::settle_jury(&env, &dispute);
+                }
+                Self::release_appeal_bond(&env, &lease, &dispute, true);
+                Self::settle_dispute(&env, &mut lease, &mut dispute, refund_percentage);
+            } else {
+                let refund_amount = Self::escrowed(&lease) * refund_percentage / 100;
+                Self::close_lease(&env, &mut lease, refund_amount);
+            }
+            log!(&env, "Escrow released for lease: {}", lease_id);
+        }
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        return true;
+    }
+    
+    // Submit a review for an asset
+    pub fn submit_review(
+        env: Env,
+        asset_id: u64,
+        reviewer: Address,
+        rating: u64,
+        comment: String
+    ) -> u64 {
+        // Verify the caller is the reviewer
+        reviewer.require_auth();
+        
+        // Verify rating is valid (0-100)
+        if rating > 100 {
+            log!(&env, "Rating must be between 0 and 100");
+            panic!("Rating must be between 0 and 100");
+        }
+        
+        // Get current timestamp
+        let time = env.ledger().timestamp();
+        
+        // Get current review counter
+        let mut review_counter: u64 = env.storage().instance().get(&REVIEW_COUNTER).unwrap_or(0);
+        review_counter += 1;
+        
+        // Create new review
+        let review = Review {
+            review_id: review_counter,
+            asset_id,
+            reviewer: reviewer.clone(),
+            rating,
+            comment,
+            review_time: time
+        };
+        
+        // Store the review
+        env.storage().instance().set(&ReviewRegistry::Review(review_counter), &review);
+        
+        // Update counter
+        env.storage().instance().set(&REVIEW_COUNTER, &review_counter);
+        
+        // Update asset rating (average of all ratings)
+        // In a real implementation, you would calculate the average of all reviews
+        // Here, we'll just update directly
+        let mut asset = Self::get_asset(env.clone(), asset_id);
+        asset.rating = rating;  // Simplified - should be average of all ratings
+        env.storage().instance().set(&AssetRegistry::Asset(asset_id), &asset);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Review submitted: {}", review_counter);
+        return review_counter;
+    }
+    
+    // Raise a dispute for a lease
+    pub fn raise_dispute(
+        env: Env,
+        lease_id: u64,
+        caller: Address
+    ) -> bool {
+        // Verify the caller is either the lessor or lessee
+        caller.require_auth();
+        
+        // Get the lease
+        let mut lease = Self::get_lease(env.clone(), lease_id);
+        
+        // Verify caller is either lessor or lessee
+        if lease.lessor != caller && lease.lessee != caller {
+            log!(&env, "Only the lessor or lessee can raise a dispute");
+            panic!("Only the lessor or lessee can raise a dispute");
+        }
+        
+        // Verify lease is active
+        if !lease.is_active {
+            log!(&env, "Cannot raise dispute on inactive lease");
+            panic!("Cannot raise dispute on inactive lease");
+        }
+        
+        // Verify no dispute is already pending
+        if lease.dispute_raised {
+            log!(&env, "Dispute already raised for this lease");
+            panic!("Dispute already raised for this lease");
+        }
+        
+        let dispute = Self::open_dispute(&env, &mut lease, &caller, false);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Dispute raised for lease: {}, assigned to arbitrator: {}", lease_id, dispute.arbitrator);
+        return true;
+    }
+    
+    // Resolve a dispute for a lease
+    pub fn resolve_dispute(
+        env: Env,
+        lease_id: u64,
+        caller: Address,
+        refund_percentage: u64
+    ) -> bool {
+        // Verify the caller is the assigned arbitrator or the admin
+        caller.require_auth();
+        
+        // Verify refund percentage is valid (0-100)
+        if refund_percentage > 100 {
+            log!(&env, "Refund percentage must be between 0 and 100");
+            panic!("Refund percentage must be between 0 and 100");
+        }
+        
+        // Get the lease
+        let mut lease = Self::get_lease(env.clone(), lease_id);
+        
+        // Verify dispute was raised
+        if !lease.dispute_raised {
+            log!(&env, "No dispute raised for this lease");
+            panic!("No dispute raised for this lease");
+        }
+        
+        let mut dispute = Self::get_dispute(env.clone(), lease.dispute_id);
+        
+        // Verify the dispute is not already awaiting its appeal window
+        if dispute.is_resolved {
+            log!(&env, "Dispute already resolved");
+            panic!("Dispute already resolved");
+        }
+        
+        // Jury disputes apply the median of the revealed votes once the reveal phase is over
+        let mut jury_outcome = None;
+        if !dispute.jury.is_empty() {
+            if env.ledger().timestamp() < dispute.reveal_deadline {
+                log!(&env, "Jury voting is still open");
+                panic!("Jury voting is still open");
+            }
+            jury_outcome = Self::settle_jury(&env, &dispute);
+        }
+        
+        let refund_percentage = match jury_outcome {
+            Some(median) => median,
+            None => {
+                // Only the assigned arbitrator may rule, with the admin as fallback once the timeout has passed
+                if caller != dispute.arbitrator {
+                    let admin = Self::get_admin(env.clone());
+                    let timed_out = env.ledger().timestamp() >= dispute.raised_time + ARBITRATION_TIMEOUT;
+                    if caller != admin || !timed_out {
+                        log!(&env, "Only the assigned arbitrator can resolve this dispute");
+                        panic!("Only the assigned arbitrator can resolve this dispute");
+                    }
+                }
+                
+                // Update statistics of whoever decided the case
+                let mut arb_stats = Self::get_arbitrator_stats(env.clone(), caller.clone());
+                arb_stats.cases_decided += 1;
+                arb_stats.total_refund += refund_percentage;
+                arb_stats.average_refund = arb_stats.total_refund / arb_stats.cases_decided;
+                env.storage().instance().set(&ArbitratorRegistry::Stats(caller), &arb_stats);
+                
+                refund_percentage
+            }
+        };
+        
+        // Record the ruling on the dispute
+        dispute.is_resolved = true;
+        dispute.refund_percentage = refund_percentage;
+        dispute.ruling_time = env.ledger().timestamp();
+        env.storage().instance().set(&DisputeRegistry::Dispute(dispute.dispute_id), &dispute);
+        
+        // Appeal rulings are final; first rulings are paid out once the appeal window closes
+        if dispute.is_appealed || Self::get_dispute_config(env.clone()).appeal_window == 0 {
+            Self::execute_ruling(&env, &mut lease, &mut dispute);
+        }
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Dispute resolved for lease: {}", lease_id);
+        return true;
+    }
+    
+    // Appeal a ruling within the appeal window by posting a bond
+    pub fn appeal(env: Env, dispute_id: u64, caller: Address) -> bool {
+        // Verify the caller is either the lessor or lessee
+        caller.require_auth();
+        
+        let mut dispute = Self::get_dispute(env.clone(), dispute_id);
+        let lease = Self::get_lease(env.clone(), dispute.lease_id);
+        
+        if lease.lessor != caller && lease.lessee != caller {
+            log!(&env, "Only the lessor or lessee can appeal");
+            panic!("Only the lessor or lessee can appeal");
+        }
+        
+        // Verify there is a ruling that is still within its appeal window
+        let config = Self::get_dispute_config(env.clone());
+        let time = env.ledger().timestamp();
+        if !dispute.is_resolved || dispute.is_settled || time >= dispute.ruling_time + config.appeal_window {
+            log!(&env, "Ruling cannot be appealed");
+            panic!("Ruling cannot be appealed");
+        }
+        
+        // Each dispute can be appealed only once
+        if dispute.is_appealed {
+            log!(&env, "Dispute already appealed");
+            panic!("Dispute already appealed");
+        }
+        
+        // Post the appeal bond into escrow
+        let bond = (Self::disputed_amount(&lease, &dispute) * config.appeal_bond_percentage / 100) as i128;
+        if bond > 0 {
+            token::Client::new(&env, &lease.token).transfer(&caller, &env.current_contract_address(), &bond);
+        }
+        
+        // Escalate to a larger jury for jury disputes, otherwise to the admin
+        let previous_jury = dispute.jury.clone();
+        dispute.jury = Vec::new(&env);
+        if !previous_jury.is_empty() {
+            if let Some(jury_config) = Self::get_jury_config_opt(&env) {
+                dispute.jury = Self::draw_jurors(&env, &lease, jury_config.panel_size * 2, &previous_jury);
+            }
+        }
+        if dispute.jury.is_empty() {
+            dispute.arbitrator = Self::get_admin(env.clone());
+            dispute.commit_deadline = 0;
+            dispute.reveal_deadline = 0;
+        } else {
+            let jury_config = Self::get_jury_config(env.clone());
+            dispute.commit_deadline = time + jury_config.commit_period;
+            dispute.reveal_deadline = time + jury_config.commit_period + jury_config.reveal_period;
+        }
+        
+        // Reopen the dispute for the appeal round
+        dispute.is_appealed = true;
+        dispute.appellant = caller.clone();
+        dispute.appeal_bond = bond;
+        dispute.appealed_refund = dispute.refund_percentage;
+        dispute.is_resolved = false;
+        dispute.responded = true;
+        dispute.resolution_deadline = (time + config.resolution_window).max(dispute.reveal_deadline);
+        env.storage().instance().set(&DisputeRegistry::Dispute(dispute_id), &dispute);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Dispute appealed: {}", dispute_id);
+        return true;
+    }
+    
+    // Submit evidence for a dispute (responding in time prevents a default outcome)
+    pub fn submit_evidence(env: Env, dispute_id: u64, caller: Address, content: String) -> bool {
+        // Verify the caller is either the lessor or lessee
+        caller.require_auth();
+        
+        let mut dispute = Self::get_dispute(env.clone(), dispute_id);
+        let lease = Self::get_lease(env.clone(), dispute.lease_id);
+        
+        if lease.lessor != caller && lease.lessee != caller {
+            log!(&env, "Only the lessor or lessee can submit evidence");
+            panic!("Only the lessor or lessee can submit evidence");
+        }
+        if dispute.is_resolved {
+            log!(&env, "Dispute already resolved");
+            panic!("Dispute already resolved");
+        }
+        
+        // Store the evidence
+        let time = env.ledger().timestamp();
+        let mut evidence = Self::get_evidence(env.clone(), dispute_id);
+        evidence.push_back(Evidence {
+            submitter: caller.clone(),
+            content,
+            submitted_time: time
+        });
+        env.storage().instance().set(&DisputeRegistry::Evidence(dispute_id), &evidence);
+        
+        // Record a timely response from the counterparty
+        if caller != dispute.raised_by && time < dispute.response_deadline {
+            dispute.responded = true;
+            env.storage().instance().set(&DisputeRegistry::Dispute(dispute_id), &dispute);
+        }
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Evidence submitted for dispute: {}", dispute_id);
+        return true;
+    }
+    
+    // Finalize a stalled dispute once its deadlines have passed (callable by anyone)
+    pub fn finalize_dispute(env: Env, dispute_id: u64) -> u64 {
+        let mut dispute = Self::get_dispute(env.clone(), dispute_id);
+        let mut lease = Self::get_lease(env.clone(), dispute.lease_id);
+        
+        if dispute.is_settled {
+            log!(&env, "Dispute already settled");
+            panic!("Dispute already settled");
+        }
+        
+        let time = env.ledger().timestamp();
+        
+        // A ruling is paid out once its appeal window has closed
+        if dispute.is_resolved {
+            if time < dispute.ruling_time + Self::get_dispute_config(env.clone()).appeal_window {
+                log!(&env, "Appeal window still open");
+                panic!("Appeal window still open");
+            }
+            Self::execute_ruling(&env, &mut lease, &mut dispute);
+            
+            // Update contract TTL
+            env.storage().instance().extend_ttl(5000, 5000);
+            
+            log!(&env, "Dispute finalized: {}", dispute_id);
+            return dispute.refund_percentage;
+        }
+        
+        let refund_percentage = if dispute.is_appealed && time >= dispute.resolution_deadline {
+            // Nobody ruled on the appeal in time - the jury median or the original ruling stands
+            let jury_outcome = if dispute.jury.is_empty() { None } else { Self::settle_jury(&env, &dispute) };
+            let refund_percentage = jury_outcome.unwrap_or(dispute.appealed_refund);
+            Self::release_appeal_bond(&env, &lease, &dispute, true);
+            refund_percentage
+        } else if !dispute.responded && time >= dispute.response_deadline {
+            // The counterparty never responded - default outcome in favour of the raiser
+            if !dispute.jury.is_empty() {
+                Self::settle_jury(&env, &dispute);
+            }
+            if dispute.raised_by == lease.lessee { 100 } else { 0 }
+        } else if time >= dispute.resolution_deadline {
+            // Nobody ruled in time - apply the jury median if there is one, otherwise the fallback policy
+            let jury_outcome = if dispute.jury.is_empty() { None } else { Self::settle_jury(&env, &dispute) };
+            match jury_outcome {
+                Some(median) => median,
+                None => match Self::get_dispute_config(env.clone()).fallback_policy {
+                    FallbackPolicy::RefundLessee => 100,
+                    FallbackPolicy::ReleaseToLessor => 0,
+                    FallbackPolicy::Split => 50
+                }
+            }
+        } else {
+            log!(&env, "Dispute cannot be finalized yet");
+            panic!("Dispute cannot be finalized yet");
+        };
+        
+        // Apply the outcome to escrow and close the lease
+        Self::settle_dispute(&env, &mut lease, &mut dispute, refund_percentage);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Dispute finalized: {}", dispute_id);
+        return refund_percentage;
+    }
+    
+    // Configure dispute deadlines and fallback policy (admin only)
+    pub fn configure_disputes(env: Env, admin: Address, config: DisputeConfig) -> bool {
+        Self::require_admin(&env, &admin);
+        
+        if config.response_window == 0 || config.resolution_window < config.response_window
+            || config.appeal_bond_percentage > 100 {
+            log!(&env, "Invalid dispute configuration");
+            panic!("Invalid dispute configuration");
+        }
+        env.storage().instance().set(&DISPUTE_CONFIG, &config);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Dispute windows configured");
+        return true;
+    }
+    
+    // Set the default pricing token for new assets, adding it to the allowlist (admin only)
+    pub fn set_payment_token(env: Env, admin: Address, token: Address) -> bool {
+        Self::require_admin(&env, &admin);
+        
+        env.storage().instance().set(&PAYMENT_TOKEN, &token);
+        Self::add_allowed_token(&env, &token);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Payment token set: {}", token);
+        return true;
+    }
+    
+    // Accept a token for asset pricing (admin only)
+    pub fn allow_token(env: Env, admin: Address, token: Address) -> bool {
+        Self::require_admin(&env, &admin);
+        
+        Self::add_allowed_token(&env, &token);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Token allowed: {}", token);
+        return true;
+    }
+    
+    // Stop accepting a token for new leases (admin only) - existing leases settle in their token
+    pub fn disallow_token(env: Env, admin: Address, token: Address) -> bool {
+        Self::require_admin(&env, &admin);
+        
+        if token == Self::default_token(&env) {
+            log!(&env, "Cannot disallow the default payment token");
+            panic!("Cannot disallow the default payment token");
+        }
+        let mut tokens = Self::get_allowed_tokens(env.clone());
+        let index = tokens.first_index_of(&token).unwrap_or_else(|| {
+            log!(&env, "Token not allowed: {}", token);
+            panic!("Token not allowed");
+        });
+        tokens.remove(index);
+        env.storage().instance().set(&ALLOWED_TOKENS, &tokens);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Token disallowed: {}", token);
+        return true;
+    }
+    
+    // Set the token an asset is priced in (owner only, must be allowlisted)
+    pub fn set_asset_token(env: Env, asset_id: u64, owner: Address, token: Address) -> bool {
+        // Verify the caller is the owner
+        owner.require_auth();
+        
+        let mut asset = Self::get_asset(env.clone(), asset_id);
+        if asset.owner != owner {
+            log!(&env, "Only the owner can update the asset");
+            panic!("Only the owner can update the asset");
+        }
+        if !Self::get_allowed_tokens(env.clone()).contains(&token) {
+            log!(&env, "Token not allowed: {}", token);
+            panic!("Token not allowed");
+        }
+        
+        // Existing leases keep the token they were created with
+        asset.token = token;
+        env.storage().instance().set(&AssetRegistry::Asset(asset_id), &asset);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Pricing token set for asset: {}", asset_id);
+        return true;
+    }
+    
+    // Configure the price oracle used for reference-currency pricing (admin only)
+    pub fn set_price_oracle(env: Env, admin: Address, oracle: Address, max_age: u64) -> bool {
+        Self::require_admin(&env, &admin);
+        
+        if max_age == 0 {
+            log!(&env, "Maximum price age must be positive");
+            panic!("Maximum price age must be positive");
+        }
+        env.storage().instance().set(&ORACLE_CONFIG, &OracleConfig { oracle, max_age });
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Price oracle configured");
+        return true;
+    }
+    
+    // Set the attestation contract consulted for lessee credentials (admin only)
+    pub fn set_attestation_contract(env: Env, admin: Address, attestation: Address) -> bool {
+        Self::require_admin(&env, &admin);
+        
+        env.storage().instance().set(&ATTESTATION, &attestation);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Attestation contract configured");
+        return true;
+    }
+    
+    // Require lessees of an asset to hold a credential from the attestation contract (owner only)
+    pub fn set_asset_credential(
+        env: Env,
+        asset_id: u64,
+        owner: Address,
+        requires_credential: bool,
+        credential_type: Symbol
+    ) -> bool {
+        // Verify the caller is the owner
+        owner.require_auth();
+        
+        let mut asset = Self::get_asset(env.clone(), asset_id);
+        if asset.owner != owner {
+            log!(&env, "Only the owner can update the asset");
+            panic!("Only the owner can update the asset");
+        }
+        if requires_credential {
+            Self::get_attestation_contract(env.clone());
+        }
+        
+        asset.requires_credential = requires_credential;
+        asset.credential_type = credential_type;
+        env.storage().instance().set(&AssetRegistry::Asset(asset_id), &asset);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Credential requirement updated for asset: {}", asset_id);
+        return true;
+    }
+    
+    // Price an asset in the oracle's reference currency instead of its token (owner only).
+    // The asset price is then read as reference-currency units at the token's decimals.
+    pub fn set_asset_fiat_pricing(env: Env, asset_id: u64, owner: Address, priced_in_fiat: bool) -> bool {
+        // Verify the caller is the owner
+        owner.require_auth();
+        
+        let mut asset = Self::get_asset(env.clone(), asset_id);
+        if asset.owner != owner {
+            log!(&env, "Only the owner can update the asset");
+            panic!("Only the owner can update the asset");
+        }
+        if priced_in_fiat && !env.storage().instance().has(&ORACLE_CONFIG) {
+            log!(&env, "Price oracle not configured");
+            panic!("Price oracle not configured");
+        }
+        
+        asset.priced_in_fiat = priced_in_fiat;
+        env.storage().instance().set(&AssetRegistry::Asset(asset_id), &asset);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Fiat pricing updated for asset: {}", asset_id);
+        return true;
+    }
+    
+    // Quote the total cost of leasing an asset's capabilities for a duration, in the asset's pricing token
+    pub fn quote_lease(env: Env, asset_id: u64, duration: u64, scopes: Vec<Symbol>) -> u64 {
+        let asset = Self::get_asset(env.clone(), asset_id);
+        let scopes = Self::resolve_scopes(&env, &asset, scopes);
+        return Self::lease_cost(&env, &asset, duration, &scopes);
+    }
+    
+    // Declare the capabilities (channels) of an asset and their per-unit prices (owner only)
+    pub fn set_asset_capabilities(env: Env, asset_id: u64, owner: Address, capabilities: Vec<Capability>) -> bool {
+        // Verify the caller is the owner
+        owner.require_auth();
+        
+        let mut asset = Self::get_asset(env.clone(), asset_id);
+        if asset.owner != owner {
+            log!(&env, "Only the owner can update the asset");
+            panic!("Only the owner can update the asset");
+        }
+        
+        // Capability names must be unique
+        let mut names: Vec<Symbol> = Vec::new(&env);
+        for capability in capabilities.iter() {
+            if names.contains(&capability.name) {
+                log!(&env, "Duplicate capability: {}", capability.name);
+                panic!("Duplicate capability");
+            }
+            names.push_back(capability.name);
+        }
+        
+        asset.capabilities = capabilities;
+        env.storage().instance().set(&AssetRegistry::Asset(asset_id), &asset);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Capabilities updated for asset: {}", asset_id);
+        return true;
+    }
+    
+    // Set the price tiers and duration discounts of an asset (owner only)
+    pub fn set_asset_pricing(
+        env: Env,
+        asset_id: u64,
+        owner: Address,
+        price_tiers: Vec<PriceTier>,
+        duration_discounts: Vec<DurationDiscount>
+    ) -> bool {
+        // Verify the caller is the owner
+        owner.require_auth();
+        
+        let mut asset = Self::get_asset(env.clone(), asset_id);
+        if asset.owner != owner {
+            log!(&env, "Only the owner can update the asset");
+            panic!("Only the owner can update the asset");
+        }
+        if asset.payment_model == PaymentModel::PayPerUse && !price_tiers.is_empty() {
+            log!(&env, "Price tiers require a time-based payment model");
+            panic!("Price tiers require a time-based payment model");
+        }
+        
+        // Tiers must start after the first unit and be strictly ascending
+        let mut last_unit = 0;
+        for tier in price_tiers.iter() {
+            if tier.from_unit <= last_unit {
+                log!(&env, "Price tiers must be in ascending order");
+                panic!("Price tiers must be in ascending order");
+            }
+            last_unit = tier.from_unit;
+        }
+        
+        // Discounts must be strictly ascending by duration and at most 100%
+        let mut last_duration = 0;
+        for discount in duration_discounts.iter() {
+            if discount.min_duration <= last_duration || discount.percent_off > 100 {
+                log!(&env, "Invalid duration discount");
+                panic!("Invalid duration discount");
+            }
+            last_duration = discount.min_duration;
+        }
+        
+        asset.price_tiers = price_tiers;
+        asset.duration_discounts = duration_discounts;
+        env.storage().instance().set(&AssetRegistry::Asset(asset_id), &asset);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Pricing updated for asset: {}", asset_id);
+        return true;
+    }
+    
+    // Set the cancellation policy and lessor early-termination penalty of an asset (owner only)
+    pub fn set_cancellation_policy(
+        env: Env,
+        asset_id: u64,
+        owner: Address,
+        policy: CancellationPolicy,
+        termination_penalty: u64
+    ) -> bool {
+        // Verify the caller is the owner
+        owner.require_auth();
+        
+        let mut asset = Self::get_asset(env.clone(), asset_id);
+        if asset.owner != owner {
+            log!(&env, "Only the owner can update the asset");
+            panic!("Only the owner can update the asset");
+        }
+        if termination_penalty > 100 {
+            log!(&env, "Penalty percentage must be between 0 and 100");
+            panic!("Penalty percentage must be between 0 and 100");
+        }
+        
+        // Existing leases keep the terms they were created with
+        asset.cancellation_policy = policy;
+        asset.termination_penalty = termination_penalty;
+        env.storage().instance().set(&AssetRegistry::Asset(asset_id), &asset);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Cancellation policy updated for asset: {}", asset_id);
+        return true;
+    }
+    
+    // Stream lease payments to the lessor per second instead of up front (owner only)
+    pub fn set_asset_streaming(env: Env, asset_id: u64, owner: Address, streaming: bool) -> bool {
+        // Verify the caller is the owner
+        owner.require_auth();
+        
+        let mut asset = Self::get_asset(env.clone(), asset_id);
+        if asset.owner != owner {
+            log!(&env, "Only the owner can update the asset");
+            panic!("Only the owner can update the asset");
+        }
+        if streaming && asset.payment_model == PaymentModel::PayPerUse {
+            log!(&env, "Pay-per-use assets cannot stream payments");
+            panic!("Pay-per-use assets cannot stream payments");
+        }
+        
+        // Existing leases keep the settlement mode they were created with
+        asset.streaming = streaming;
+        env.storage().instance().set(&AssetRegistry::Asset(asset_id), &asset);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Streaming updated for asset: {}", asset_id);
+        return true;
+    }
+    
+    // Release the portion of a streaming lease earned so far to the lessor
+    pub fn claim_streamed(env: Env, lease_id: u64, lessor: Address) -> u64 {
+        // Verify the caller is the lessor
+        lessor.require_auth();
+        
+        let mut lease = Self::get_lease(env.clone(), lease_id);
+        if lease.lessor != lessor {
+            log!(&env, "Only the lessor can claim streamed payments");
+            panic!("Only the lessor can claim streamed payments");
+        }
+        
+        // Disputed and multi-signature escrow cannot be released unilaterally
+        if !lease.is_streaming || !lease.is_active || !lease.is_paid || lease.dispute_raised
+            || env.storage().instance().has(&LeaseRegistry::Release(lease_id)) {
+            log!(&env, "No streamed payment can be claimed");
+            panic!("No streamed payment can be claimed");
+        }
+        
+        let amount = Self::get_accrued(env.clone(), lease_id);
+        if amount > 0 {
+            lease.released += amount;
+            env.storage().instance().set(&LeaseRegistry::Lease(lease_id), &lease);
+            
+            let asset = Self::get_asset(env.clone(), lease.asset_id);
+            Self::pay_lessor(&env, &lease, &asset.asset_type, amount);
+        }
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Streamed payment claimed for lease: {}", lease_id);
+        return amount;
+    }
+    
+    // Set the security deposit lessees must post for a physical asset
+    pub fn set_asset_deposit(env: Env, asset_id: u64, owner: Address, deposit: u64) -> bool {
+        // Verify the caller is the owner
+        owner.require_auth();
+        
+        let mut asset = Self::get_asset(env.clone(), asset_id);
+        if asset.owner != owner {
+            log!(&env, "Only the owner can update the asset");
+            panic!("Only the owner can update the asset");
+        }
+        if asset.asset_type != String::from_str(&env, "Physical") {
+            log!(&env, "Deposits are only supported for physical assets");
+            panic!("Deposits are only supported for physical assets");
+        }
+        
+        asset.deposit = deposit;
+        env.storage().instance().set(&AssetRegistry::Asset(asset_id), &asset);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Deposit set for asset: {}", asset_id);
+        return true;
+    }
+    
+    // File a damage claim against the security deposit of a closed lease (lessor only)
+    pub fn file_damage_claim(env: Env, lease_id: u64, lessor: Address, evidence: String) -> u64 {
+        // Verify the caller is the lessor
+        lessor.require_auth();
+        
+        let mut lease = Self::get_lease(env.clone(), lease_id);
+        if lease.lessor != lessor {
+            log!(&env, "Only the lessor can file a damage claim");
+            panic!("Only the lessor can file a damage claim");
+        }
+        
+        // Verify the lease has closed, the deposit is still held and the claim window is open
+        // (a pending request is inactive but has not closed, so its window has not started)
+        let window = Self::get_dispute_config(env.clone()).damage_claim_window;
+        if lease.is_active || lease.is_pending || lease.deposit_settled || lease.dispute_raised
+            || env.ledger().timestamp() >= lease.closed_time + window {
+            log!(&env, "Deposit cannot be claimed");
+            panic!("Deposit cannot be claimed");
+        }
+        
+        let dispute = Self::open_dispute(&env, &mut lease, &lessor, true);
+        
+        // Store the lessor's evidence with the claim
+        let mut submitted = Vec::new(&env);
+        submitted.push_back(Evidence {
+            submitter: lessor,
+            content: evidence,
+            submitted_time: env.ledger().timestamp()
+        });
+        env.storage().instance().set(&DisputeRegistry::Evidence(dispute.dispute_id), &submitted);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Damage claim filed for lease: {}", lease_id);
+        return dispute.dispute_id;
+    }
+    
+    // Return the security deposit once the damage claim window has passed (callable by anyone)
+    pub fn release_deposit(env: Env, lease_id: u64) -> bool {
+        let mut lease = Self::get_lease(env.clone(), lease_id);
+        
+        // Pending requests hold the deposit until they are approved or declined
+        let window = Self::get_dispute_config(env.clone()).damage_claim_window;
+        if lease.is_active || lease.is_pending || lease.deposit_settled || lease.dispute_raised
+            || env.ledger().timestamp() < lease.closed_time + window {
+            log!(&env, "Deposit cannot be released yet");
+            panic!("Deposit cannot be released yet");
+        }
+        
+        Self::settle_deposit(&env, &mut lease, 100);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Deposit released for lease: {}", lease_id);
+        return true;
+    }
+    
+    // Set the default platform fee in basis points (admin only)
+    pub fn set_platform_fee(env: Env, admin: Address, fee_bps: u32) -> bool {
+        Self::require_admin(&env, &admin);
+        
+        if fee_bps > MAX_FEE_BPS {
+            log!(&env, "Fee must be at most 10000 basis points");
+            panic!("Fee must be at most 10000 basis points");
+        }
+        env.storage().instance().set(&PLATFORM_FEE, &fee_bps);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Platform fee set: {}", fee_bps);
+        return true;
+    }
+    
+    // Override the platform fee for an asset type (admin only)
+    pub fn set_asset_type_fee(env: Env, admin: Address, asset_type: String, fee_bps: u32) -> bool {
+        Self::require_admin(&env, &admin);
+        
+        if fee_bps > MAX_FEE_BPS {
+            log!(&env, "Fee must be at most 10000 basis points");
+            panic!("Fee must be at most 10000 basis points");
+        }
+        env.storage().instance().set(&FeeRegistry::AssetType(asset_type), &fee_bps);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Asset type fee set: {}", fee_bps);
+        return true;
+    }
+    
+    // Remove the platform fee override for an asset type (admin only)
+    pub fn clear_asset_type_fee(env: Env, admin: Address, asset_type: String) -> bool {
+        Self::require_admin(&env, &admin);
+        
+        env.storage().instance().remove(&FeeRegistry::AssetType(asset_type));
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Asset type fee cleared");
+        return true;
+    }
+    
+    // Withdraw accrued platform fees in a token from the treasury (admin only)
+    pub fn withdraw_fees(env: Env, admin: Address, token: Address, to: Address, amount: u64) -> bool {
+        Self::require_admin(&env, &admin);
+        
+        let treasury = Self::get_treasury_balance(env.clone(), token.clone());
+        if amount == 0 || amount > treasury {
+            log!(&env, "Insufficient treasury balance");
+            panic!("Insufficient treasury balance");
+        }
+        env.storage().instance().set(&FeeRegistry::Treasury(token.clone()), &(treasury - amount));
+        
+        token::Client::new(&env, &token).transfer(&env.current_contract_address(), &to, &(amount as i128));
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Fees withdrawn: {}", amount);
+        return true;
+    }
+    
+    // Withdraw settled funds credited to the caller
+    pub fn withdraw(env: Env, holder: Address, token: Address, amount: i128) -> i128 {
+        // Verify the caller is the holder
+        holder.require_auth();
+        
+        let mut balances = Self::get_claimable(env.clone(), holder.clone());
+        let available = balances.get(token.clone()).unwrap_or(0);
+        if amount <= 0 || amount > available {
+            log!(&env, "Insufficient claimable balance");
+            panic!("Insufficient claimable balance");
+        }
+        
+        // Update the ledger before transferring out
+        let remaining = available - amount;
+        if remaining == 0 {
+            balances.remove(token.clone());
+        } else {
+            balances.set(token.clone(), remaining);
+        }
+        env.storage().instance().set(&ClaimRegistry::Claimable(holder.clone()), &balances);
+        
+        token::Client::new(&env, &token).transfer(&env.current_contract_address(), &holder, &amount);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Withdrawal: {}", amount);
+        return remaining;
+    }
+    
+    // Register a new arbitrator (admin only)
+    pub fn add_arbitrator(env: Env, admin: Address, arbitrator: Address) -> bool {
+        Self::require_admin(&env, &admin);
+        
+        let mut arbitrators = Self::get_arbitrators(env.clone());
+        if arbitrators.contains(&arbitrator) {
+            log!(&env, "Arbitrator already registered");
+            panic!("Arbitrator already registered");
+        }
+        arbitrators.push_back(arbitrator.clone());
+        env.storage().instance().set(&ARBITRATORS, &arbitrators);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Arbitrator added: {}", arbitrator);
+        return true;
+    }
+    
+    // Remove an arbitrator (admin only) - disputes already assigned keep their arbitrator
+    pub fn remove_arbitrator(env: Env, admin: Address, arbitrator: Address) -> bool {
+        Self::require_admin(&env, &admin);
+        
+        let mut arbitrators = Self::get_arbitrators(env.clone());
+        let index = arbitrators.first_index_of(&arbitrator).unwrap_or_else(|| {
+            log!(&env, "Arbitrator not found: {}", arbitrator);
+            panic!("Arbitrator not found");
+        });
+        arbitrators.remove(index);
+        env.storage().instance().set(&ARBITRATORS, &arbitrators);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Arbitrator removed: {}", arbitrator);
+        return true;
+    }
+    
+    // Configure jury voting for high-value disputes (admin only)
+    pub fn configure_jury(env: Env, admin: Address, config: JuryConfig) -> bool {
+        Self::require_admin(&env, &admin);
+        
+        // Verify configuration is valid
+        if config.panel_size == 0 || config.min_stake <= 0 || config.commit_period == 0 || config.reveal_period == 0 {
+            log!(&env, "Invalid jury configuration");
+            panic!("Invalid jury configuration");
+        }
+        if config.slash_percentage > 100 {
+            log!(&env, "Slash percentage must be between 0 and 100");
+            panic!("Slash percentage must be between 0 and 100");
+        }
+        
+        // Staked funds are held in the current token, so it cannot change while jurors are staked
+        if let Some(current) = Self::get_jury_config_opt(&env) {
+            if current.stake_token != config.stake_token && !Self::get_jurors(env.clone()).is_empty() {
+                log!(&env, "Cannot change stake token while jurors are staked");
+                panic!("Cannot change stake token while jurors are staked");
+            }
+        }
+        
+        env.storage().instance().set(&JURY_CONFIG, &config);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Jury configured");
+        return true;
+    }
+    
+    // Stake tokens to become eligible for jury selection
+    pub fn stake_juror(env: Env, juror: Address, amount: i128) -> i128 {
+        // Verify the caller is the juror
+        juror.require_auth();
+        
+        if amount <= 0 {
+            log!(&env, "Stake amount must be positive");
+            panic!("Stake amount must be positive");
+        }
+        
+        // Move the stake into the contract
+        let config = Self::get_jury_config(env.clone());
+        token::Client::new(&env, &config.stake_token).transfer(&juror, &env.current_contract_address(), &amount);
+        
+        // Update the juror record and pool
+        let mut record = Self::get_juror(env.clone(), juror.clone());
+        record.stake += amount;
+        env.storage().instance().set(&JuryRegistry::Juror(juror.clone()), &record);
+        
+        let mut jurors = Self::get_jurors(env.clone());
+        if !jurors.contains(&juror) {
+            jurors.push_back(juror.clone());
+            env.storage().instance().set(&JURORS, &jurors);
+        }
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Juror staked: {}", juror);
+        return record.stake;
+    }
+    
+    // Withdraw staked tokens (only while not sitting on a pending dispute)
+    pub fn unstake_juror(env: Env, juror: Address, amount: i128) -> i128 {
+        // Verify the caller is the juror
+        juror.require_auth();
+        
+        let mut record = Self::get_juror(env.clone(), juror.clone());
+        if record.active_cases > 0 {
+            log!(&env, "Stake is locked while serving on a jury");
+            panic!("Stake is locked while serving on a jury");
+        }
+        if amount <= 0 || amount > record.stake {
+            log!(&env, "Invalid unstake amount");
+            panic!("Invalid unstake amount");
+        }
+        
+        // Update the juror record and pool
+        record.stake -= amount;
+        env.storage().instance().set(&JuryRegistry::Juror(juror.clone()), &record);
+        
+        if record.stake == 0 {
+            let mut jurors = Self::get_jurors(env.clone());
+            if let Some(index) = jurors.first_index_of(&juror) {
+                jurors.remove(index);
+                env.storage().instance().set(&JURORS, &jurors);
+            }
+        }
+        
+        // Return the stake to the juror
+        let config = Self::get_jury_config(env.clone());
+        token::Client::new(&env, &config.stake_token).transfer(&env.current_contract_address(), &juror, &amount);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Juror unstaked: {}", juror);
+        return record.stake;
+    }
+    
+    // Commit a hidden jury vote during the commit phase
+    pub fn commit_vote(env: Env, dispute_id: u64, juror: Address, commitment: BytesN<32>) -> bool {
+        // Verify the caller is the juror
+        juror.require_auth();
+        
+        let dispute = Self::get_dispute(env.clone(), dispute_id);
+        
+        // Verify the juror sits on the panel and the commit phase is open
+        if !dispute.jury.contains(&juror) {
+            log!(&env, "Only selected jurors can vote");
+            panic!("Only selected jurors can vote");
+        }
+        if dispute.is_resolved || env.ledger().timestamp() >= dispute.commit_deadline {
+            log!(&env, "Commit phase is closed");
+            panic!("Commit phase is closed");
+        }
+        
+        // Store the commitment (jurors may replace it until the phase closes)
+        let vote = JuryVote {
+            commitment,
+            revealed: false,
+            refund_percentage: 0
+        };
+        env.storage().instance().set(&JuryRegistry::Vote(dispute_id, juror.clone()), &vote);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Vote committed for dispute: {}", dispute_id);
+        return true;
+    }
+    
+    // Reveal a committed jury vote during the reveal phase
+    pub fn reveal_vote(
+        env: Env,
+        dispute_id: u64,
+        juror: Address,
+        refund_percentage: u64,
+        salt: BytesN<32>
+    ) -> bool {
+        // Verify the caller is the juror
+        juror.require_auth();
+        
+        let dispute = Self::get_dispute(env.clone(), dispute_id);
+        
+        // Verify the reveal phase is open
+        let time = env.ledger().timestamp();
+        if dispute.is_resolved || time < dispute.commit_deadline || time >= dispute.reveal_deadline {
+            log!(&env, "Reveal phase is closed");
+            panic!("Reveal phase is closed");
+        }
+        
+        // Verify the revealed vote matches the commitment
+        let mut vote = Self::get_jury_vote(env.clone(), dispute_id, juror.clone());
+        let expected = Self::compute_vote_commitment(env.clone(), juror.clone(), refund_percentage, salt);
+        if vote.revealed || vote.commitment != expected {
+            log!(&env, "Vote does not match commitment");
+            panic!("Vote does not match commitment");
+        }
+        if refund_percentage > 100 {
+            log!(&env, "Refund percentage must be between 0 and 100");
+            panic!("Refund percentage must be between 0 and 100");
+        }
+        
+        vote.revealed = true;
+        vote.refund_percentage = refund_percentage;
+        env.storage().instance().set(&JuryRegistry::Vote(dispute_id, juror), &vote);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Vote revealed for dispute: {}", dispute_id);
+        return true;
+    }
+    
+    // Create a discount code for one asset or, with owner_wide, all of the owner's assets
+    pub fn create_coupon(
+        env: Env,
+        owner: Address,
+        owner_wide: bool,
+        asset_id: u64,
+        code_hash: BytesN<32>,  // sha256 of the code lessees will present
+        percent_off: u64,
+        max_uses: u32,
+        expiry: u64
+    ) -> bool {
+        // Verify the caller is the owner
+        owner.require_auth();
+        
+        if !owner_wide && Self::get_asset(env.clone(), asset_id).owner != owner {
+            log!(&env, "Only the owner can create coupons for the asset");
+            panic!("Only the owner can create coupons for the asset");
+        }
+        if percent_off == 0 || percent_off > 100 || max_uses == 0 || expiry <= env.ledger().timestamp() {
+            log!(&env, "Invalid coupon");
+            panic!("Invalid coupon");
+        }
+        
+        let key = CouponRegistry::Coupon(owner.clone(), code_hash);
+        if env.storage().instance().has(&key) {
+            log!(&env, "Coupon already exists");
+            panic!("Coupon already exists");
+        }
+        
+        let coupon = Coupon {
+            owner,
+            owner_wide,
+            asset_id,
+            percent_off,
+            max_uses,
+            uses: 0,
+            expiry
+        };
+        env.storage().instance().set(&key, &coupon);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(&env, "Coupon created");
+        return true;
+    }
+    
+    // Get auction details
+    pub fn get_auction(env: Env, auction_id: u64) -> Auction {
+        let key = AuctionRegistry::Auction(auction_id);
+        env.storage().instance().get(&key).unwrap_or_else(|| {
+            log!(&env, "Auction not found: {}", auction_id);
+            panic!("Auction not found");
+        })
+    }
+    
+    // Get offer details
+    pub fn get_offer(env: Env, offer_id: u64) -> Offer {
+        let key = OfferRegistry::Offer(offer_id);
+        env.storage().instance().get(&key).unwrap_or_else(|| {
+            log!(&env, "Offer not found: {}", offer_id);
+            panic!("Offer not found");
+        })
+    }
+    
+    // Get a coupon by owner and code hash
+    pub fn get_coupon(env: Env, owner: Address, code_hash: BytesN<32>) -> Coupon {
+        let key = CouponRegistry::Coupon(owner.clone(), code_hash);
+        env.storage().instance().get(&key).unwrap_or_else(|| {
+            log!(&env, "Coupon not found for owner: {}", owner);
+            panic!("Coupon not found");
+        })
+    }
+    
+    // Compute the hash under which a coupon code is stored
+    pub fn compute_coupon_hash(env: Env, code: Bytes) -> BytesN<32> {
+        env.crypto().sha256(&code).to_bytes()
+    }
+    
+    // Compute the commitment a juror submits for a vote
+    pub fn compute_vote_commitment(env: Env, juror: Address, refund_percentage: u64, salt: BytesN<32>) -> BytesN<32> {
+        let mut data = Bytes::from_array(&env, &refund_percentage.to_be_bytes());
+        data.append(&salt.into());
+        data.append(&juror.to_xdr(&env));
+        env.crypto().sha256(&data).to_bytes()
+    }
+    
+    // Get assets by owner
+    pub fn get_assets_by_owner(env: Env, owner: Address) -> Vec<Asset> {
+        let asset_counter: u64 = env.storage().instance().get(&ASSET_COUNTER).unwrap_or(0);
+        let mut assets = Vec::new(&env);
+        
+        // Iterate through all assets and find those owned by the specified owner
+        for i in 1..=asset_counter {
+            let asset = Self::get_asset(env.clone(), i);
+            if asset.owner == owner {
+                assets.push_back(asset);
+            }
+        }
+        
+        return assets;
+    }
+    
+    // Get active leases by lessee
+    pub fn get_leases_by_lessee(env: Env, lessee: Address) -> Vec<Lease> {
+        let lease_counter: u64 = env.storage().instance().get(&LEASE_COUNTER).unwrap_or(0);
+        let mut leases = Vec::new(&env);
+        
+        // Iterate through all leases and find active ones for the specified lessee
+        for i in 1..=lease_counter {
+            let lease = Self::get_lease(env.clone(), i);
+            if lease.lessee == lessee && lease.is_active {
+                leases.push_back(lease);
+            }
+        }
+        
+        return leases;
+    }
+    
+    // Get available assets by type
+    pub fn get_available_assets_by_type(env: Env, asset_type: String) -> Vec<Asset> {
+        let asset_counter: u64 = env.storage().instance().get(&ASSET_COUNTER).unwrap_or(0);
+        let mut assets = Vec::new(&env);
+        
+        // Iterate through all assets and find available ones of the specified type
+        for i in 1..=asset_counter {
+            let asset = Self::get_asset(env.clone(), i);
+            if asset.is_available && asset.asset_type == asset_type {
+                assets.push_back(asset);
+            }
+        }
+        
+        return assets;
+    }
+    
+    // Helper function to get asset stats
+    pub fn get_asset_stats(env: Env) -> AssetStats {
+        env.storage().instance().get(&ALL_ASSETS).unwrap_or(AssetStats {
+            available: 0,
+            leased: 0,
+            registered: 0,
+            revenue: Map::new(&env)
+        })
+    }
+    
+    // Helper function to get an asset by ID
+    pub fn get_asset(env: Env, asset_id: u64) -> Asset {
+        let key = AssetRegistry::Asset(asset_id);
+        env.storage().instance().get(&key).unwrap_or_else(|| {
+            log!(&env, "Asset not found: {}", asset_id);
+            panic!("Asset not found");
+        })
+    }
+    
+    // Helper function to get a lease by ID
+    pub fn get_lease(env: Env, lease_id: u64) -> Lease {
+        let key = LeaseRegistry::Lease(lease_id);
+        env.storage().instance().get(&key).unwrap_or_else(|| {
+            log!(&env, "Lease not found: {}", lease_id);
+            panic!("Lease not found");
+        })
+    }
+    
+    // Helper function to get the subleases created under a lease
+    pub fn get_subleases(env: Env, lease_id: u64) -> Vec<u64> {
+        env.storage().instance().get(&LeaseRegistry::Subleases(lease_id)).unwrap_or(Vec::new(&env))
+    }
+    
+    // Helper function to get the multi-signature release settings of a lease
+    pub fn get_multisig_release(env: Env, lease_id: u64) -> MultiSigRelease {
+        let key = LeaseRegistry::Release(lease_id);
+        env.storage().instance().get(&key).unwrap_or_else(|| {
+            log!(&env, "Multi-signature release not enabled for lease: {}", lease_id);
+            panic!("Multi-signature release not enabled");
+        })
+    }
+    
+    // Helper function to get the streamed payment a lessor can currently claim on a lease
+    pub fn get_accrued(env: Env, lease_id: u64) -> u64 {
+        let lease = Self::get_lease(env.clone(), lease_id);
+        if !lease.is_streaming || !lease.is_active || !lease.is_paid {
+            return 0;
+        }
+        return Self::elapsed_cost(&env, &lease) - lease.released;
     }
     
     // Helper function to get a review by ID
     pub fn get_review(env: Env, review_id: u64) -> Review {
         let key = ReviewRegistry::Review(review_id);
         env.storage().instance().get(&key).unwrap_or_else(|| {
-            log!(&env, "Review not found: {}", review_id);
-            panic!("Review not found");
+            log!(&env, "Review not found: {}", review_id);
+            panic!("Review not found");
+        })
+    }
+    
+    // Helper function to get a dispute by ID
+    pub fn get_dispute(env: Env, dispute_id: u64) -> Dispute {
+        let key = DisputeRegistry::Dispute(dispute_id);
+        env.storage().instance().get(&key).unwrap_or_else(|| {
+            log!(&env, "Dispute not found: {}", dispute_id);
+            panic!("Dispute not found");
+        })
+    }
+    
+    // Helper function to get the evidence submitted for a dispute
+    pub fn get_evidence(env: Env, dispute_id: u64) -> Vec<Evidence> {
+        env.storage().instance().get(&DisputeRegistry::Evidence(dispute_id)).unwrap_or(Vec::new(&env))
+    }
+    
+    // Helper function to get the dispute windows and fallback policy
+    pub fn get_dispute_config(env: Env) -> DisputeConfig {
+        env.storage().instance().get(&DISPUTE_CONFIG).unwrap_or(DisputeConfig {
+            response_window: DEFAULT_RESPONSE_WINDOW,
+            resolution_window: DEFAULT_RESOLUTION_WINDOW,
+            fallback_policy: FallbackPolicy::RefundLessee,
+            appeal_window: DEFAULT_APPEAL_WINDOW,
+            appeal_bond_percentage: DEFAULT_APPEAL_BOND_PERCENTAGE,
+            damage_claim_window: DEFAULT_DAMAGE_CLAIM_WINDOW
+        })
+    }
+    
+    // Helper function to get the platform fee (basis points) applied to an asset type
+    pub fn get_platform_fee(env: Env, asset_type: String) -> u32 {
+        let key = FeeRegistry::AssetType(asset_type);
+        env.storage().instance().get(&key)
+            .unwrap_or(env.storage().instance().get(&PLATFORM_FEE).unwrap_or(0))
+    }
+    
+    // Helper function to get the platform fees accrued in the treasury for a token
+    pub fn get_treasury_balance(env: Env, token: Address) -> u64 {
+        env.storage().instance().get(&FeeRegistry::Treasury(token)).unwrap_or(0)
+    }
+    
+    // Helper function to get the price oracle configuration
+    pub fn get_oracle_config(env: Env) -> OracleConfig {
+        env.storage().instance().get(&ORACLE_CONFIG).unwrap_or_else(|| {
+            log!(&env, "Price oracle not configured");
+            panic!("Price oracle not configured");
+        })
+    }
+    
+    // Helper function to get the attestation contract
+    pub fn get_attestation_contract(env: Env) -> Address {
+        env.storage().instance().get(&ATTESTATION).unwrap_or_else(|| {
+            log!(&env, "Attestation contract not configured");
+            panic!("Attestation contract not configured");
+        })
+    }
+    
+    // Helper function to get the tokens assets can be priced in
+    pub fn get_allowed_tokens(env: Env) -> Vec<Address> {
+        env.storage().instance().get(&ALLOWED_TOKENS).unwrap_or(Vec::new(&env))
+    }
+    
+    // Helper function to get the balances (token -> amount) an address can withdraw
+    pub fn get_claimable(env: Env, holder: Address) -> Map<Address, i128> {
+        env.storage().instance().get(&ClaimRegistry::Claimable(holder)).unwrap_or(Map::new(&env))
+    }
+    
+    // Helper function to get the marketplace admin
+    pub fn get_admin(env: Env) -> Address {
+        env.storage().instance().get(&ADMIN).unwrap_or_else(|| {
+            log!(&env, "Marketplace not initialized");
+            panic!("Marketplace not initialized");
+        })
+    }
+    
+    // Helper function to get the registered arbitrators
+    pub fn get_arbitrators(env: Env) -> Vec<Address> {
+        env.storage().instance().get(&ARBITRATORS).unwrap_or(Vec::new(&env))
+    }
+    
+    // Helper function to get the statistics of an arbitrator
+    pub fn get_arbitrator_stats(env: Env, arbitrator: Address) -> ArbitratorStats {
+        let key = ArbitratorRegistry::Stats(arbitrator);
+        env.storage().instance().get(&key).unwrap_or(ArbitratorStats {
+            cases_decided: 0,
+            total_refund: 0,
+            average_refund: 0
+        })
+    }
+    
+    // Helper function to get the jury configuration
+    pub fn get_jury_config(env: Env) -> JuryConfig {
+        Self::get_jury_config_opt(&env).unwrap_or_else(|| {
+            log!(&env, "Jury not configured");
+            panic!("Jury not configured");
+        })
+    }
+    
+    // Helper function to get the staked jurors
+    pub fn get_jurors(env: Env) -> Vec<Address> {
+        env.storage().instance().get(&JURORS).unwrap_or(Vec::new(&env))
+    }
+    
+    // Helper function to get a juror record
+    pub fn get_juror(env: Env, juror: Address) -> Juror {
+        env.storage().instance().get(&JuryRegistry::Juror(juror)).unwrap_or(Juror {
+            stake: 0,
+            active_cases: 0
+        })
+    }
+    
+    // Helper function to get a juror's vote on a dispute
+    pub fn get_jury_vote(env: Env, dispute_id: u64, juror: Address) -> JuryVote {
+        let key = JuryRegistry::Vote(dispute_id, juror);
+        env.storage().instance().get(&key).unwrap_or_else(|| {
+            log!(&env, "Vote not found for dispute: {}", dispute_id);
+            panic!("Vote not found");
+        })
+    }
+}
+
+// Internal helpers (not exposed as contract functions)
+impl IoTMarketplace {
+    
+    // Verify the caller is the marketplace admin
+    fn require_admin(env: &Env, admin: &Address) {
+        admin.require_auth();
+        if *admin != Self::get_admin(env.clone()) {
+            log!(env, "Only the admin can perform this action");
+            panic!("Only the admin can perform this action");
+        }
+    }
+    
+    // Pick the next arbitrator in round-robin order, skipping parties to the lease
+    fn assign_arbitrator(env: &Env, lease: &Lease) -> Address {
+        let arbitrators = Self::get_arbitrators(env.clone());
+        let count = arbitrators.len() as u64;
+        let cursor: u64 = env.storage().instance().get(&ARB_CURSOR).unwrap_or(0);
+        
+        for offset in 0..count {
+            let candidate = arbitrators.get(((cursor + offset) % count) as u32).unwrap();
+            if candidate != lease.lessor && candidate != lease.lessee {
+                env.storage().instance().set(&ARB_CURSOR, &(cursor + offset + 1));
+                return candidate;
+            }
+        }
+        
+        // No eligible arbitrator - the admin decides
+        return Self::get_admin(env.clone());
+    }
+    
+    // Calculate the cost of leasing an asset for a duration, in the asset's pricing token
+    fn lease_cost(env: &Env, asset: &Asset, duration: u64, scopes: &Vec<Symbol>) -> u64 {
+        return Self::try_lease_cost(env, asset, duration, scopes).unwrap_or_else(|| {
+            log!(env, "Price unavailable for asset: {}", asset.asset_id);
+            panic!("Price unavailable");
+        });
+    }
+    
+    // Calculate the cost of leasing an asset for a duration, or None if a fiat price cannot be converted
+    fn try_lease_cost(env: &Env, asset: &Asset, duration: u64, scopes: &Vec<Symbol>) -> Option<u64> {
+        // Calculate the number of billing units based on payment model and duration
+        let units = match asset.payment_model {
+            PaymentModel::Hourly => duration / 3600,
+            PaymentModel::Daily => duration / 86400,
+            PaymentModel::Weekly => duration / 604800,
+            PaymentModel::Monthly => duration / 2592000,
+            PaymentModel::PayPerUse => 1
+        };
+        
+        // Charge each unit at the price of the tier it falls in
+        let mut cost = 0;
+        let mut start = 0;
+        let mut price = asset.price;
+        for tier in asset.price_tiers.iter() {
+            if tier.from_unit >= units {
+                break;
+            }
+            cost += price * (tier.from_unit - start);
+            start = tier.from_unit;
+            price = tier.price;
+        }
+        cost += price * (units - start);
+        
+        // Add the per-unit price of each granted capability
+        for capability in asset.capabilities.iter() {
+            if scopes.contains(&capability.name) {
+                cost += capability.price * units;
+            }
+        }
+        
+        // Apply the longest duration discount the lease qualifies for
+        let mut percent_off = 0;
+        for discount in asset.duration_discounts.iter() {
+            if duration >= discount.min_duration {
+                percent_off = discount.percent_off;
+            }
+        }
+        cost -= cost * percent_off / 100;
+        
+        if asset.priced_in_fiat {
+            return Self::convert_from_reference(env, &asset.token, cost);
+        }
+        return Some(cost);
+    }
+    
+    // Validate and redeem a coupon for a new lease, returning the discount
+    fn redeem_coupon(env: &Env, asset: &Asset, lease_id: u64, code: Bytes, cost: u64) -> u64 {
+        let code_hash = env.crypto().sha256(&code).to_bytes();
+        let key = CouponRegistry::Coupon(asset.owner.clone(), code_hash.clone());
+        let mut coupon: Coupon = env.storage().instance().get(&key).unwrap_or_else(|| {
+            log!(env, "Invalid coupon");
+            panic!("Invalid coupon");
+        });
+        
+        if (!coupon.owner_wide && coupon.asset_id != asset.asset_id)
+            || coupon.uses >= coupon.max_uses || env.ledger().timestamp() >= coupon.expiry {
+            log!(env, "Coupon cannot be redeemed");
+            panic!("Coupon cannot be redeemed");
+        }
+        
+        coupon.uses += 1;
+        env.storage().instance().set(&key, &coupon);
+        
+        let discount = cost * coupon.percent_off / 100;
+        env.events().publish((symbol_short!("coupon"), asset.owner.clone(), code_hash), (lease_id, asset.asset_id, discount));
+        return discount;
+    }
+    
+    // Set or clear an access list entry after verifying the caller owns the asset (or list)
+    fn set_access_flag(env: &Env, owner: &Address, owner_wide: bool, asset_id: u64, key: AccessListRegistry, value: bool) {
+        // Verify the caller is the owner
+        owner.require_auth();
+        
+        if !owner_wide && Self::get_asset(env.clone(), asset_id).owner != *owner {
+            log!(env, "Only the owner can update the asset");
+            panic!("Only the owner can update the asset");
+        }
+        
+        if value {
+            env.storage().instance().set(&key, &true);
+        } else {
+            env.storage().instance().remove(&key);
+        }
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+    }
+    
+    // Blocked lessees are always refused; in allowlist-only mode the lessee must be allowlisted
+    // Assets requiring a credential also need it attested for the lessee
+    fn lessee_permitted(env: &Env, asset: &Asset, lessee: &Address) -> bool {
+        let storage = env.storage().instance();
+        if storage.has(&AccessListRegistry::AssetBlocked(asset.asset_id, lessee.clone()))
+            || storage.has(&AccessListRegistry::OwnerBlocked(asset.owner.clone(), lessee.clone())) {
+            return false;
+        }
+        
+        if asset.requires_credential {
+            let attestation = AttestationClient::new(env, &Self::get_attestation_contract(env.clone()));
+            if !attestation.has_credential(lessee, &asset.credential_type) {
+                return false;
+            }
+        }
+        
+        let allowlist_only = storage.has(&AccessListRegistry::AssetAllowlistOnly(asset.asset_id))
+            || storage.has(&AccessListRegistry::OwnerAllowlistOnly(asset.owner.clone()));
+        if !allowlist_only {
+            return true;
+        }
+        return storage.has(&AccessListRegistry::AssetAllowed(asset.asset_id, lessee.clone()))
+            || storage.has(&AccessListRegistry::OwnerAllowed(asset.owner.clone(), lessee.clone()));
+    }
+    
+    // Panic unless the asset's access lists and credential requirement permit the lessee
+    fn require_permitted(env: &Env, asset: &Asset, lessee: &Address) {
+        if !Self::lessee_permitted(env, asset, lessee) {
+            log!(env, "Lessee is not permitted to lease this asset");
+            panic!("Lessee is not permitted to lease this asset");
+        }
+    }
+    
+    // Validate negotiated terms: the offer must expire no later than the lease window opens
+    fn check_offer_terms(env: &Env, start: u64, duration: u64, expiry: u64) {
+        if expiry <= env.ledger().timestamp() || start < expiry || duration == 0 {
+            log!(env, "Invalid offer terms");
+            panic!("Invalid offer terms");
+        }
+    }
+    
+    // Record a lease as the lessee's current lease of its asset for access checks
+    fn index_lease(env: &Env, lease: &Lease) {
+        env.storage().instance().set(&LeaseRegistry::Active(lease.asset_id, lease.lessee.clone()), &lease.lease_id);
+    }
+    
+    // Remove a closed lease from the (asset, lessee) index, unless the entry now points to another lease
+    fn unindex_lease(env: &Env, lease: &Lease) {
+        let key = LeaseRegistry::Active(lease.asset_id, lease.lessee.clone());
+        if env.storage().instance().get::<_, u64>(&key) == Some(lease.lease_id) {
+            env.storage().instance().remove(&key);
+        }
+    }
+    
+    // Store a new access key ciphertext for an active lease and bump its key version
+    fn store_access_key(env: &Env, mut lease: Lease, lessor: &Address, ciphertext: Bytes) -> u32 {
+        // Verify the caller is the lessor
+        lessor.require_auth();
+        
+        if lease.lessor != *lessor {
+            log!(env, "Only the lessor can deliver access keys");
+            panic!("Only the lessor can deliver access keys");
+        }
+        if !lease.is_active || lease.access_revoked {
+            log!(env, "Lease is not active or access was revoked");
+            panic!("Lease is not active or access was revoked");
+        }
+        if lease.encryption_key == BytesN::from_array(env, &[0; 32]) {
+            log!(env, "Lessee has not set an encryption key");
+            panic!("Lessee has not set an encryption key");
+        }
+        
+        lease.key_version += 1;
+        env.storage().instance().set(&LeaseRegistry::Lease(lease.lease_id), &lease);
+        env.storage().instance().set(&LeaseRegistry::AccessKey(lease.lease_id), &ciphertext);
+        
+        // Update contract TTL
+        env.storage().instance().extend_ttl(5000, 5000);
+        
+        log!(env, "Access key version {} stored for lease: {}", lease.key_version, lease.lease_id);
+        return lease.key_version;
+    }
+    
+    // Validate requested capabilities against the asset; an empty request selects all of them
+    fn resolve_scopes(env: &Env, asset: &Asset, requested: Vec<Symbol>) -> Vec<Symbol> {
+        let mut scopes = Vec::new(env);
+        for capability in asset.capabilities.iter() {
+            if requested.is_empty() || requested.contains(&capability.name) {
+                scopes.push_back(capability.name);
+            }
+        }
+        if scopes.len() != requested.len() && !requested.is_empty() {
+            log!(env, "Unknown or duplicate capability requested");
+            panic!("Unknown or duplicate capability requested");
+        }
+        return scopes;
+    }
+    
+    // Build an unpaid lease of an asset on the asset's current terms
+    fn new_lease(
+        env: &Env,
+        asset: &Asset,
+        lease_id: u64,
+        lessee: &Address,
+        start_time: u64,
+        end_time: u64,
+        total_cost: u64,
+        deposit: u64,
+        encryption_key: BytesN<32>,
+        scopes: Vec<Symbol>
+    ) -> Lease {
+        return Lease {
+            lease_id,
+            asset_id: asset.asset_id,
+            lessor: asset.owner.clone(),
+            lessee: lessee.clone(),
+            start_time,
+            end_time,
+            total_cost,
+            token: asset.token.clone(),
+            is_active: true,
+            is_paid: false,  // Will be set to true after payment
+            encryption_key,
+            key_version: 0,
+            access_revoked: false,
+            signing_key: BytesN::from_array(env, &[0; 32]),
+            scopes,
+            parent_lease_id: 0,
+            owner_share: 0,
+            dispute_raised: false,
+            dispute_id: 0,
+            deposit,
+            deposit_settled: deposit == 0,
+            closed_time: 0,
+            is_streaming: asset.streaming,
+            released: 0,
+            cancellation_policy: asset.cancellation_policy.clone(),
+            termination_penalty: asset.termination_penalty,
+            auto_renew: false,
+            renewal_period: 0,
+            is_pending: false,
+            approval_deadline: 0
+        };
+    }
+    
+    // Convert a reference-currency amount into the token using a fresh oracle price (rounded up).
+    // The amount is in reference-currency units at the token's decimals, e.g. 1 USD = 10^7 for a 7-decimal token.
+    // Returns None (logging why) if no oracle is configured, the oracle call fails, the price is missing,
+    // invalid or stale, or the converted amount overflows, so batch callers can skip the asset
+    fn convert_from_reference(env: &Env, token: &Address, amount: u64) -> Option<u64> {
+        let config: OracleConfig = match env.storage().instance().get(&ORACLE_CONFIG) {
+            Some(config) => config,
+            None => {
+                log!(env, "Price oracle not configured");
+                return None;
+            }
+        };
+        let oracle = PriceOracleClient::new(env, &config.oracle);
+        
+        let data = match oracle.try_lastprice(&OracleAsset::Stellar(token.clone())) {
+            Ok(Ok(Some(data))) => data,
+            _ => {
+                log!(env, "No oracle price for token: {}", token);
+                return None;
+            }
+        };
+        if data.price <= 0 {
+            log!(env, "Invalid oracle price");
+            return None;
+        }
+        if env.ledger().timestamp() > data.timestamp.saturating_add(config.max_age) {
+            log!(env, "Oracle price is stale");
+            return None;
+        }
+        
+        let converted = match oracle.try_decimals() {
+            Ok(Ok(decimals)) => 10i128.checked_pow(decimals)
+                .and_then(|scale| (amount as i128).checked_mul(scale))
+                .map(|scaled| (scaled + data.price - 1) / data.price),
+            _ => None
+        };
+        let converted = converted.and_then(|converted| u64::try_from(converted).ok());
+        if converted.is_none() {
+            log!(env, "Converted price overflows");
+        }
+        return converted;
+    }
+    
+    // Get the default pricing token for new assets
+    fn default_token(env: &Env) -> Address {
+        env.storage().instance().get(&PAYMENT_TOKEN).unwrap_or_else(|| {
+            log!(env, "Payment token not configured");
+            panic!("Payment token not configured");
         })
     }
-}
\ No newline at end of file
+    
+    // Add a token to the pricing allowlist
+    fn add_allowed_token(env: &Env, token: &Address) {
+        let mut tokens = Self::get_allowed_tokens(env.clone());
+        if !tokens.contains(token) {
+            tokens.push_back(token.clone());
+            env.storage().instance().set(&ALLOWED_TOKENS, &tokens);
+        }
+    }
+    
+    // Credit a settlement payout to the holder's claimable balance
+    fn credit(env: &Env, holder: &Address, token: &Address, amount: i128) {
+        if amount <= 0 {
+            return;
+        }
+        let mut balances = Self::get_claimable(env.clone(), holder.clone());
+        let current = balances.get(token.clone()).unwrap_or(0);
+        balances.set(token.clone(), current + amount);
+        env.storage().instance().set(&ClaimRegistry::Claimable(holder.clone()), &balances);
+    }
+    
+    // Create a dispute on a lease, assigning an arbitrator and (for high-value disputes) a jury
+    fn open_dispute(env: &Env, lease: &mut Lease, raised_by: &Address, is_damage_claim: bool) -> Dispute {
+        // Get current dispute counter
+        let mut dispute_counter: u64 = env.storage().instance().get(&DISPUTE_COUNTER).unwrap_or(0);
+        dispute_counter += 1;
+        
+        // Subleases lose access while their parent is disputed
+        Self::end_subleases(env, lease);
+        
+        // Assign an arbitrator who is not a party to the lease
+        let arbitrator = Self::assign_arbitrator(env, lease);
+        
+        // High-value disputes additionally go to a jury
+        let time = env.ledger().timestamp();
+        let disputed = if is_damage_claim { lease.deposit } else { lease.total_cost };
+        let jury = Self::select_jury(env, lease, disputed);
+        let (commit_deadline, reveal_deadline) = match Self::get_jury_config_opt(env) {
+            Some(config) if !jury.is_empty() => {
+                (time + config.commit_period, time + config.commit_period + config.reveal_period)
+            }
+            _ => (0, 0)
+        };
+        
+        // A ruling is never due before the jury has finished voting
+        let config = Self::get_dispute_config(env.clone());
+        let resolution_deadline = (time + config.resolution_window).max(reveal_deadline);
+        
+        // Create new dispute
+        let dispute = Dispute {
+            dispute_id: dispute_counter,
+            lease_id: lease.lease_id,
+            raised_by: raised_by.clone(),
+            arbitrator,
+            raised_time: time,
+            is_resolved: false,
+            refund_percentage: 0,
+            jury,
+            commit_deadline,
+            reveal_deadline,
+            responded: false,
+            response_deadline: time + config.response_window,
+            resolution_deadline,
+            ruling_time: 0,
+            is_appealed: false,
+            appellant: raised_by.clone(),
+            appeal_bond: 0,
+            appealed_refund: 0,
+            is_settled: false,
+            is_damage_claim
+        };
+        env.storage().instance().set(&DisputeRegistry::Dispute(dispute_counter), &dispute);
+        env.storage().instance().set(&DISPUTE_COUNTER, &dispute_counter);
+        
+        // Mark dispute as raised
+        lease.dispute_raised = true;
+        lease.dispute_id = dispute_counter;
+        env.storage().instance().set(&LeaseRegistry::Lease(lease.lease_id), &*lease);
+        
+        return dispute;
+    }
+    
+    // Amount of escrow at stake in a dispute
+    fn disputed_amount(lease: &Lease, dispute: &Dispute) -> u64 {
+        if dispute.is_damage_claim { lease.deposit } else { lease.total_cost }
+    }
+    
+    // Close a lease: release remaining escrow (refunding the given amount to the lessee) and free the asset
+    fn close_lease(env: &Env, lease: &mut Lease, refund_amount: u64) {
+        lease.is_active = false;
+        lease.dispute_raised = false;
+        lease.closed_time = env.ledger().timestamp();
+        env.storage().instance().set(&LeaseRegistry::Lease(lease.lease_id), &*lease);
+        env.storage().instance().remove(&LeaseRegistry::AccessKey(lease.lease_id));
+        Self::unindex_lease(env, lease);
+        Self::end_subleases(env, lease);
+        
+        // Update asset availability (a sublease never held the asset itself)
+        let mut asset = Self::get_asset(env.clone(), lease.asset_id);
+        let mut stats = Self::get_asset_stats(env.clone());
+        if lease.parent_lease_id == 0 {
+            asset.is_available = true;
+            env.storage().instance().set(&AssetRegistry::Asset(lease.asset_id), &asset);
+            stats.available += 1;
+            stats.leased -= 1;
+        }
+        
+        // Pay out escrow if the lease was paid
+        if lease.is_paid {
+            let gross = Self::escrowed(lease) - refund_amount;
+            Self::credit(env, &lease.lessee, &lease.token, refund_amount as i128);
+            Self::pay_lessor(env, lease, &asset.asset_type, gross);
+            // Adjust revenue based on refund
+            let revenue = stats.revenue.get(lease.token.clone()).unwrap_or(0);
+            stats.revenue.set(lease.token.clone(), revenue - refund_amount);
+        }
+        env.storage().instance().set(&ALL_ASSETS, &stats);
+    }
+    
+    // Terminate the active subleases of a lease, refunding each sublessee's unused time
+    fn end_subleases(env: &Env, lease: &Lease) {
+        for sublease_id in Self::get_subleases(env.clone(), lease.lease_id).iter() {
+            let mut sublease = Self::get_lease(env.clone(), sublease_id);
+            if !sublease.is_active || sublease.dispute_raised {
+                continue;
+            }
+            let unused = sublease.total_cost - Self::elapsed_cost(env, &sublease);
+            let refund = unused.min(Self::escrowed(&sublease));
+            Self::close_lease(env, &mut sublease, refund);
+            log!(env, "Sublease terminated: {}", sublease_id);
+        }
+    }
+    
+    // Escrow still held for a lease (total cost less anything already streamed to the lessor)
+    fn escrowed(lease: &Lease) -> u64 {
+        lease.total_cost - lease.released
+    }
+    
+    // Credit the lessor's share of escrow, deducting the platform fee into the treasury
+    fn pay_lessor(env: &Env, lease: &Lease, asset_type: &String, gross: u64) {
+        let fee_bps = Self::get_platform_fee(env.clone(), asset_type.clone());
+        let fee = gross * fee_bps as u64 / MAX_FEE_BPS as u64;
+        if fee > 0 {
+            let treasury = Self::get_treasury_balance(env.clone(), lease.token.clone());
+            env.storage().instance().set(&FeeRegistry::Treasury(lease.token.clone()), &(treasury + fee));
+        }
+        
+        // Subleases pay the asset owner its share of the reseller's proceeds
+        let net = gross - fee;
+        let share = net * lease.owner_share / 100;
+        if share > 0 {
+            let owner = Self::get_asset(env.clone(), lease.asset_id).owner;
+            Self::credit(env, &owner, &lease.token, share as i128);
+        }
+        Self::credit(env, &lease.lessor, &lease.token, (net - share) as i128);
+    }
+    
+    // Refund owed to the lessee from escrow when the caller ends a lease
+    // Lessor-initiated early termination refunds the whole unused portion (the penalty is collected separately),
+    // lessee-initiated termination refunds per the cancellation policy (streaming leases always settle exactly)
+    fn cancellation_refund(env: &Env, lease: &Lease, caller: &Address) -> u64 {
+        if env.ledger().timestamp() >= lease.end_time {
+            return 0;
+        }
+        
+        let unused = lease.total_cost - Self::elapsed_cost(env, lease);
+        if *caller == lease.lessor {
+            return unused.min(Self::escrowed(lease));
+        }
+        
+        let asset = Self::get_asset(env.clone(), lease.asset_id);
+        if asset.payment_model == PaymentModel::PayPerUse {
+            return 0;
+        }
+        let refund = if lease.is_streaming {
+            unused
+        } else {
+            match lease.cancellation_policy {
+                CancellationPolicy::Flexible => unused,
+                CancellationPolicy::Moderate => unused / 2,
+                CancellationPolicy::Strict => 0
+            }
+        };
+        return refund.min(Self::escrowed(lease));
+    }
+    
+    // Collect the early termination penalty from the lessor and credit it to the lessee,
+    // drawing on the lessor's claimable balance first and their wallet for the rest
+    fn collect_termination_penalty(env: &Env, lease: &Lease) {
+        let penalty = (lease.total_cost * lease.termination_penalty / 100) as i128;
+        if penalty == 0 {
+            return;
+        }
+        
+        let mut balances = Self::get_claimable(env.clone(), lease.lessor.clone());
+        let available = balances.get(lease.token.clone()).unwrap_or(0);
+        let from_balance = available.min(penalty);
+        if from_balance == available {
+            balances.remove(lease.token.clone());
+        } else {
+            balances.set(lease.token.clone(), available - from_balance);
+        }
+        env.storage().instance().set(&ClaimRegistry::Claimable(lease.lessor.clone()), &balances);
+        
+        if penalty > from_balance {
+            token::Client::new(env, &lease.token).transfer(&lease.lessor, &env.current_contract_address(), &(penalty - from_balance));
+        }
+        Self::credit(env, &lease.lessee, &lease.token, penalty);
+        log!(env, "Termination penalty paid: {}", penalty);
+    }
+    
+    // Portion of a lease's cost corresponding to the time elapsed so far
+    fn elapsed_cost(env: &Env, lease: &Lease) -> u64 {
+        let duration = lease.end_time - lease.start_time;
+        let now = env.ledger().timestamp().min(lease.end_time);
+        if duration == 0 || now >= lease.end_time {
+            return lease.total_cost;
+        }
+        let elapsed = now.saturating_sub(lease.start_time);
+        return ((lease.total_cost as u128 * elapsed as u128) / duration as u128) as u64;
+    }
+    
+    // Pay out a ruling, returning the appeal bond if the appeal moved the outcome in the appellant's favour
+    fn execute_ruling(env: &Env, lease: &mut Lease, dispute: &mut Dispute) {
+        if dispute.is_appealed {
+            let succeeded = if dispute.appellant == lease.lessee {
+                dispute.refund_percentage > dispute.appealed_refund
+            } else {
+                dispute.refund_percentage < dispute.appealed_refund
+            };
+            Self::release_appeal_bond(env, lease, dispute, succeeded);
+        }
+        Self::settle_dispute(env, lease, dispute, dispute.refund_percentage);
+    }
+    
+    // Return the appeal bond to the appellant, or forfeit it to the other party
+    fn release_appeal_bond(env: &Env, lease: &Lease, dispute: &Dispute, returned: bool) {
+        if !dispute.is_appealed || dispute.appeal_bond == 0 {
+            return;
+        }
+        let recipient = if returned {
+            dispute.appellant.clone()
+        } else if dispute.appellant == lease.lessee {
+            lease.lessor.clone()
+        } else {
+            lease.lessee.clone()
+        };
+        Self::credit(env, &recipient, &lease.token, dispute.appeal_bond);
+    }
+    
+    // Record the outcome of a dispute and close its lease
+    fn settle_dispute(env: &Env, lease: &mut Lease, dispute: &mut Dispute, refund_percentage: u64) {
+        dispute.is_resolved = true;
+        dispute.is_settled = true;
+        dispute.refund_percentage = refund_percentage;
+        env.storage().instance().set(&DisputeRegistry::Dispute(dispute.dispute_id), &*dispute);
+        
+        if dispute.is_damage_claim {
+            Self::settle_deposit(env, lease, refund_percentage);
+        } else {
+            let refund_amount = Self::escrowed(lease) * refund_percentage / 100;
+            Self::close_lease(env, lease, refund_amount);
+        }
+    }
+    
+    // Pay out the security deposit, returning the given percentage to the lessee and the rest to the lessor
+    fn settle_deposit(env: &Env, lease: &mut Lease, refund_percentage: u64) {
+        lease.deposit_settled = true;
+        lease.dispute_raised = false;
+        env.storage().instance().set(&LeaseRegistry::Lease(lease.lease_id), &*lease);
+        
+        let refund_amount = (lease.deposit * refund_percentage) / 100;
+        let claimed = lease.deposit - refund_amount;
+        Self::credit(env, &lease.lessee, &lease.token, refund_amount as i128);
+        Self::credit(env, &lease.lessor, &lease.token, claimed as i128);
+    }
+    
+    // Get the jury configuration if one has been set
+    fn get_jury_config_opt(env: &Env) -> Option<JuryConfig> {
+        env.storage().instance().get(&JURY_CONFIG)
+    }
+    
+    // Randomly select a jury panel for high-value disputes (empty if not applicable)
+    fn select_jury(env: &Env, lease: &Lease, disputed: u64) -> Vec<Address> {
+        let no_jury = Vec::new(env);
+        let config = match Self::get_jury_config_opt(env) {
+            Some(config) if disputed > config.value_threshold => config,
+            _ => return no_jury
+        };
+        
+        return Self::draw_jurors(env, lease, config.panel_size, &no_jury);
+    }
+    
+    // Randomly draw a panel of staked jurors, excluding the lease parties and the given jurors
+    fn draw_jurors(env: &Env, lease: &Lease, panel_size: u32, excluded: &Vec<Address>) -> Vec<Address> {
+        let mut jury = Vec::new(env);
+        let config = Self::get_jury_config(env.clone());
+        
+        // Collect sufficiently staked jurors who are not parties to the lease
+        let mut eligible = Vec::new(env);
+        for juror in Self::get_jurors(env.clone()).iter() {
+            if juror != lease.lessor && juror != lease.lessee && !excluded.contains(&juror)
+                && Self::get_juror(env.clone(), juror.clone()).stake >= config.min_stake {
+                eligible.push_back(juror);
+            }
+        }
+        
+        // Not enough jurors - the arbitrator decides instead
+        if eligible.len() < panel_size {
+            log!(env, "Not enough eligible jurors, dispute goes to the arbitrator");
+            return jury;
+        }
+        
+        env.prng().shuffle(&mut eligible);
+        for juror in eligible.iter().take(panel_size as usize) {
+            let mut record = Self::get_juror(env.clone(), juror.clone());
+            record.active_cases += 1;
+            env.storage().instance().set(&JuryRegistry::Juror(juror.clone()), &record);
+            jury.push_back(juror);
+        }
+        return jury;
+    }
+    
+    // Release the jury, reward jurors near the median and slash the rest
+    // Returns the median refund percentage, or None if no juror revealed a vote
+    fn settle_jury(env: &Env, dispute: &Dispute) -> Option<u64> {
+        // Gather revealed votes in ascending order
+        let mut votes: Vec<u64> = Vec::new(env);
+        for juror in dispute.jury.iter() {
+            let key = JuryRegistry::Vote(dispute.dispute_id, juror.clone());
+            if let Some(vote) = env.storage().instance().get::<_, JuryVote>(&key) {
+                if vote.revealed {
+                    let index = votes.iter().position(|v| v > vote.refund_percentage).unwrap_or(votes.len() as usize);
+                    votes.insert(index as u32, vote.refund_percentage);
+                }
+            }
+        }
+        
+        // Release the jurors' locked stakes
+        for juror in dispute.jury.iter() {
+            let mut record = Self::get_juror(env.clone(), juror.clone());
+            record.active_cases -= 1;
+            env.storage().instance().set(&JuryRegistry::Juror(juror), &record);
+        }
+        
+        if votes.is_empty() {
+            return None;
+        }
+        let median = votes.get((votes.len() - 1) / 2).unwrap();
+        
+        // Slash jurors who voted away from the median or failed to reveal
+        let config = Self::get_jury_config(env.clone());
+        let mut majority = Vec::new(env);
+        let mut slashed: i128 = 0;
+        for juror in dispute.jury.iter() {
+            let key = JuryRegistry::Vote(dispute.dispute_id, juror.clone());
+            let in_majority = match env.storage().instance().get::<_, JuryVote>(&key) {
+                Some(vote) => vote.revealed && vote.refund_percentage.abs_diff(median) <= JURY_TOLERANCE,
+                None => false
+            };
+            if in_majority {
+                majority.push_back(juror);
+            } else {
+                let mut record = Self::get_juror(env.clone(), juror.clone());
+                let penalty = record.stake * config.slash_percentage as i128 / 100;
+                record.stake -= penalty;
+                slashed += penalty;
+                env.storage().instance().set(&JuryRegistry::Juror(juror), &record);
+            }
+        }
+        
+        // Share slashed stake among the majority (any remainder goes to the first of them)
+        let share = slashed / majority.len() as i128;
+        let remainder = slashed - share * majority.len() as i128;
+        for (i, juror) in majority.iter().enumerate() {
+            let mut record = Self::get_juror(env.clone(), juror.clone());
+            record.stake += share;
+            if i == 0 {
+                record.stake += remainder;
+            }
+            env.storage().instance().set(&JuryRegistry::Juror(juror), &record);
+        }
+        
+        return Some(median);
+    }
+}
+
+#[cfg(any(test, feature = "testutils"))]
+pub mod testutils;
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,1248 @@
+#![cfg(test)]
+extern crate std;
+
+use super::*;
+use ed25519_dalek::{Signer, SigningKey};
+use soroban_sdk::testutils::{storage::Persistent as _, Address as _, Ledger};
+use soroban_sdk::{token, Address, Bytes, BytesN, Env, String, Vec};
+
+// Marketplace deployed with an admin and a default payment token
+struct Setup<'a> {
+    env: Env,
+    client: IoTMarketplaceClient<'a>,
+    admin: Address,
+    token: Address
+}
+
+fn setup<'a>() -> Setup<'a> {
+    let env = Env::default();
+    env.mock_all_auths();
+    
+    let contract_id = env.register_contract(None, IoTMarketplace);
+    let client = IoTMarketplaceClient::new(&env, &contract_id);
+    let admin = Address::generate(&env);
+    client.initialize(&admin);
+    
+    let token = create_token(&env);
+    client.set_payment_token(&admin, &token);
+    
+    Setup { env, client, admin, token }
+}
+
+// Register a Stellar asset contract to use as a pricing token
+fn create_token(env: &Env) -> Address {
+    env.register_stellar_asset_contract_v2(Address::generate(env)).address()
+}
+
+// Generate an address holding an amount of a token
+fn funded_account(env: &Env, token: &Address, amount: i128) -> Address {
+    let account = Address::generate(env);
+    token::StellarAssetClient::new(env, token).mint(&account, &amount);
+    return account;
+}
+
+// Wallet balance plus claimable balance of an address in the default token
+fn total_balance(s: &Setup, holder: &Address) -> i128 {
+    let claimable = s.client.get_claimable(holder).get(s.token.clone()).unwrap_or(0);
+    return token::Client::new(&s.env, &s.token).balance(holder) + claimable;
+}
+
+// Register a digital asset with the given price and payment model
+fn register_asset(s: &Setup, owner: &Address, price: u64, payment_model: PaymentModel) -> u64 {
+    let env = &s.env;
+    return s.client.register_asset(
+        owner,
+        &String::from_str(env, "Air quality sensor"),
+        &String::from_str(env, "PM2.5 readings every minute"),
+        &String::from_str(env, "Digital"),
+        &String::from_str(env, "Digital"),
+        &price,
+        &payment_model,
+        &String::from_str(env, "99% uptime")
+    );
+}
+
+// Register a physical device paid per use
+fn register_device(s: &Setup, owner: &Address, price: u64) -> u64 {
+    let env = &s.env;
+    return s.client.register_asset(
+        owner,
+        &String::from_str(env, "Weather station"),
+        &String::from_str(env, "Rooftop station with wind and rain gauges"),
+        &String::from_str(env, "Physical"),
+        &String::from_str(env, "Berlin"),
+        &price,
+        &PaymentModel::PayPerUse,
+        &String::from_str(env, "Calibrated monthly")
+    );
+}
+
+// Register a mock oracle quoting the default token at a price scaled by 10^14
+fn price_oracle<'a>(s: &Setup<'a>, price: i128, timestamp: u64) -> testutils::MockPriceOracleClient<'a> {
+    let oracle_id = s.env.register_contract(None, testutils::MockPriceOracle);
+    let oracle = testutils::MockPriceOracleClient::new(&s.env, &oracle_id);
+    oracle.set_price(&OracleAsset::Stellar(s.token.clone()), &price, &timestamp);
+    s.client.set_price_oracle(&s.admin, &oracle_id, &300);
+    return oracle;
+}
+
+// Sign an access token with a lessee's ed25519 key
+fn sign_access_token(s: &Setup, signing_key: &SigningKey, token: &AccessToken) -> BytesN<64> {
+    let payload = s.client.access_token_payload(token);
+    let mut message = std::vec![0u8; payload.len() as usize];
+    payload.copy_into_slice(&mut message);
+    return BytesN::from_array(&s.env, &signing_key.sign(&message).to_bytes());
+}
+
+// Create a lease on the default terms
+fn create_lease(s: &Setup, asset_id: u64, lessee: &Address, duration: u64) -> u64 {
+    return s.client.create_lease(&asset_id, lessee, &duration, &encryption_key(&s.env), &u64::MAX, &None, &Vec::new(&s.env));
+}
+
+fn encryption_key(env: &Env) -> BytesN<32> {
+    BytesN::from_array(env, &[1; 32])
+}
+
+#[test]
+fn test_disputes_assigned_round_robin() {
+    let s = setup();
+    let arbitrator_1 = Address::generate(&s.env);
+    let arbitrator_2 = Address::generate(&s.env);
+    s.client.add_arbitrator(&s.admin, &arbitrator_1);
+    s.client.add_arbitrator(&s.admin, &arbitrator_2);
+    
+    let owner = Address::generate(&s.env);
+    let lessee = funded_account(&s.env, &s.token, 1000);
+    let asset_1 = register_asset(&s, &owner, 10, PaymentModel::PayPerUse);
+    let asset_2 = register_asset(&s, &owner, 10, PaymentModel::PayPerUse);
+    
+    let lease_1 = create_lease(&s, asset_1, &lessee, 3600);
+    let lease_2 = create_lease(&s, asset_2, &lessee, 3600);
+    s.client.raise_dispute(&lease_1, &lessee);
+    s.client.raise_dispute(&lease_2, &lessee);
+    
+    let dispute_1 = s.client.get_dispute(&s.client.get_lease(&lease_1).dispute_id);
+    let dispute_2 = s.client.get_dispute(&s.client.get_lease(&lease_2).dispute_id);
+    assert_eq!(dispute_1.arbitrator, arbitrator_1);
+    assert_eq!(dispute_2.arbitrator, arbitrator_2);
+}
+
+#[test]
+fn test_admin_resolves_only_after_arbitration_timeout() {
+    let s = setup();
+    let arbitrator = Address::generate(&s.env);
+    s.client.add_arbitrator(&s.admin, &arbitrator);
+    
+    let owner = Address::generate(&s.env);
+    let lessee = funded_account(&s.env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 10, PaymentModel::PayPerUse);
+    let lease_id = create_lease(&s, asset_id, &lessee, 3600);
+    s.client.process_payment(&lease_id, &lessee);
+    s.client.raise_dispute(&lease_id, &lessee);
+    
+    assert!(s.client.try_resolve_dispute(&lease_id, &s.admin, &40).is_err());
+    
+    s.env.ledger().with_mut(|li| li.timestamp += 604800);
+    s.client.resolve_dispute(&lease_id, &s.admin, &40);
+    assert_eq!(s.client.get_arbitrator_stats(&s.admin).cases_decided, 1);
+    assert_eq!(s.client.get_arbitrator_stats(&s.admin).average_refund, 40);
+}
+
+#[test]
+fn test_jury_commit_reveal_median_and_slashing() {
+    let s = setup();
+    let env = &s.env;
+    let stake_token = create_token(env);
+    s.client.configure_jury(&s.admin, &JuryConfig {
+        stake_token: stake_token.clone(),
+        min_stake: 100,
+        value_threshold: 50,
+        panel_size: 3,
+        commit_period: 100,
+        reveal_period: 100,
+        slash_percentage: 50
+    });
+    for _ in 0..4 {
+        let juror = funded_account(env, &stake_token, 1000);
+        s.client.stake_juror(&juror, &100);
+    }
+    
+    let owner = Address::generate(env);
+    let lessee = funded_account(env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 100, PaymentModel::PayPerUse);
+    let lease_id = create_lease(&s, asset_id, &lessee, 3600);
+    s.client.process_payment(&lease_id, &lessee);
+    s.client.raise_dispute(&lease_id, &lessee);
+    let dispute = s.client.get_dispute(&s.client.get_lease(&lease_id).dispute_id);
+    assert_eq!(dispute.jury.len(), 3);
+    
+    // Two jurors agree closely, the third is far from the median
+    let votes = [20u64, 30, 90];
+    for (i, juror) in dispute.jury.iter().enumerate() {
+        let salt = BytesN::from_array(env, &[i as u8; 32]);
+        let commitment = s.client.compute_vote_commitment(&juror, &votes[i], &salt);
+        s.client.commit_vote(&dispute.dispute_id, &juror, &commitment);
+    }
+    env.ledger().with_mut(|li| li.timestamp += 100);
+    for (i, juror) in dispute.jury.iter().enumerate() {
+        let salt = BytesN::from_array(env, &[i as u8; 32]);
+        s.client.reveal_vote(&dispute.dispute_id, &juror, &votes[i], &salt);
+    }
+    env.ledger().with_mut(|li| li.timestamp += 100);
+    
+    s.client.resolve_dispute(&lease_id, &Address::generate(env), &0);
+    let dispute = s.client.get_dispute(&dispute.dispute_id);
+    assert_eq!(dispute.refund_percentage, 30);
+    
+    // The outlier is slashed and the majority shares the slashed stake
+    let jury = dispute.jury;
+    assert_eq!(s.client.get_juror(&jury.get(0).unwrap()).stake, 125);
+    assert_eq!(s.client.get_juror(&jury.get(1).unwrap()).stake, 125);
+    assert_eq!(s.client.get_juror(&jury.get(2).unwrap()).stake, 50);
+    
+    s.client.unstake_juror(&jury.get(0).unwrap(), &125);
+    assert_eq!(token::Client::new(env, &stake_token).balance(&jury.get(0).unwrap()), 1025);
+}
+
+#[test]
+fn test_jury_rejects_mismatched_reveal() {
+    let s = setup();
+    let env = &s.env;
+    let stake_token = create_token(env);
+    s.client.configure_jury(&s.admin, &JuryConfig {
+        stake_token: stake_token.clone(),
+        min_stake: 100,
+        value_threshold: 50,
+        panel_size: 1,
+        commit_period: 100,
+        reveal_period: 100,
+        slash_percentage: 50
+    });
+    let juror = funded_account(env, &stake_token, 1000);
+    s.client.stake_juror(&juror, &100);
+    
+    let owner = Address::generate(env);
+    let lessee = funded_account(env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 100, PaymentModel::PayPerUse);
+    let lease_id = create_lease(&s, asset_id, &lessee, 3600);
+    s.client.raise_dispute(&lease_id, &lessee);
+    let dispute_id = s.client.get_lease(&lease_id).dispute_id;
+    
+    let salt = BytesN::from_array(env, &[7; 32]);
+    let commitment = s.client.compute_vote_commitment(&juror, &40, &salt);
+    s.client.commit_vote(&dispute_id, &juror, &commitment);
+    env.ledger().with_mut(|li| li.timestamp += 100);
+    
+    assert!(s.client.try_reveal_vote(&dispute_id, &juror, &60, &salt).is_err());
+    s.client.reveal_vote(&dispute_id, &juror, &40, &salt);
+    assert!(s.client.get_jury_vote(&dispute_id, &juror).revealed);
+}
+
+#[test]
+fn test_dispute_defaults_to_lessee_when_owner_silent() {
+    let s = setup();
+    let owner = Address::generate(&s.env);
+    let lessee = funded_account(&s.env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 100, PaymentModel::PayPerUse);
+    let lease_id = create_lease(&s, asset_id, &lessee, 3600);
+    s.client.process_payment(&lease_id, &lessee);
+    assert_eq!(total_balance(&s, &lessee), 900);
+    
+    s.client.raise_dispute(&lease_id, &lessee);
+    let dispute_id = s.client.get_lease(&lease_id).dispute_id;
+    s.env.ledger().with_mut(|li| li.timestamp += 259200);
+    
+    assert_eq!(s.client.finalize_dispute(&dispute_id), 100);
+    assert_eq!(total_balance(&s, &lessee), 1000);
+    assert!(s.client.get_asset(&asset_id).is_available);
+}
+
+#[test]
+fn test_dispute_fallback_split_when_unresolved() {
+    let s = setup();
+    s.client.configure_disputes(&s.admin, &DisputeConfig {
+        response_window: 10,
+        resolution_window: 100,
+        fallback_policy: FallbackPolicy::Split,
+        appeal_window: 0,
+        appeal_bond_percentage: 10,
+        damage_claim_window: 10
+    });
+    let owner = Address::generate(&s.env);
+    let lessee = funded_account(&s.env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 100, PaymentModel::PayPerUse);
+    let lease_id = create_lease(&s, asset_id, &lessee, 3600);
+    s.client.process_payment(&lease_id, &lessee);
+    s.client.raise_dispute(&lease_id, &lessee);
+    
+    let dispute_id = s.client.get_lease(&lease_id).dispute_id;
+    s.client.submit_evidence(&dispute_id, &owner, &String::from_str(&s.env, "ipfs://evidence"));
+    assert!(s.client.get_dispute(&dispute_id).responded);
+    assert!(s.client.try_finalize_dispute(&dispute_id).is_err());
+    
+    s.env.ledger().with_mut(|li| li.timestamp += 100);
+    assert_eq!(s.client.finalize_dispute(&dispute_id), 50);
+    assert_eq!(total_balance(&s, &lessee), 950);
+    assert_eq!(total_balance(&s, &owner), 50);
+}
+
+#[test]
+fn test_ended_lease_pays_owner() {
+    let s = setup();
+    let owner = Address::generate(&s.env);
+    let lessee = funded_account(&s.env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 100, PaymentModel::PayPerUse);
+    let lease_id = create_lease(&s, asset_id, &lessee, 3600);
+    s.client.process_payment(&lease_id, &lessee);
+    s.client.end_lease(&lease_id, &lessee);
+    
+    assert_eq!(total_balance(&s, &owner), 100);
+    assert_eq!(s.client.get_asset_stats().revenue.get(s.token.clone()), Some(100));
+}
+
+#[test]
+fn test_ruling_executes_after_appeal_window() {
+    let s = setup();
+    let arbitrator = Address::generate(&s.env);
+    s.client.add_arbitrator(&s.admin, &arbitrator);
+    let owner = Address::generate(&s.env);
+    let lessee = funded_account(&s.env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 100, PaymentModel::PayPerUse);
+    let lease_id = create_lease(&s, asset_id, &lessee, 3600);
+    s.client.process_payment(&lease_id, &lessee);
+    s.client.raise_dispute(&lease_id, &lessee);
+    let dispute_id = s.client.get_lease(&lease_id).dispute_id;
+    
+    // The ruling is held until the appeal window closes
+    s.client.resolve_dispute(&lease_id, &arbitrator, &20);
+    assert!(!s.client.get_asset(&asset_id).is_available);
+    assert!(s.client.try_finalize_dispute(&dispute_id).is_err());
+    
+    s.env.ledger().with_mut(|li| li.timestamp += 259200);
+    assert_eq!(s.client.finalize_dispute(&dispute_id), 20);
+    assert_eq!(total_balance(&s, &lessee), 920);
+    assert_eq!(total_balance(&s, &owner), 80);
+}
+
+#[test]
+fn test_appeal_bond_follows_final_ruling() {
+    let s = setup();
+    let arbitrator = Address::generate(&s.env);
+    s.client.add_arbitrator(&s.admin, &arbitrator);
+    let owner = Address::generate(&s.env);
+    let lessee = funded_account(&s.env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 100, PaymentModel::PayPerUse);
+    
+    // Successful appeal: the bond is returned with the new refund
+    let lease_id = create_lease(&s, asset_id, &lessee, 3600);
+    s.client.process_payment(&lease_id, &lessee);
+    s.client.raise_dispute(&lease_id, &lessee);
+    let dispute_id = s.client.get_lease(&lease_id).dispute_id;
+    s.client.resolve_dispute(&lease_id, &arbitrator, &20);
+    s.client.appeal(&dispute_id, &lessee);
+    assert_eq!(total_balance(&s, &lessee), 890);
+    assert_eq!(s.client.get_dispute(&dispute_id).arbitrator, s.admin);
+    
+    s.client.resolve_dispute(&lease_id, &s.admin, &60);
+    assert_eq!(total_balance(&s, &lessee), 960);
+    assert_eq!(total_balance(&s, &owner), 40);
+    assert!(s.client.get_asset(&asset_id).is_available);
+    
+    // Failed appeal: the bond is forfeited to the lessor
+    let lease_id = create_lease(&s, asset_id, &lessee, 3600);
+    s.client.process_payment(&lease_id, &lessee);
+    s.client.raise_dispute(&lease_id, &lessee);
+    let dispute_id = s.client.get_lease(&lease_id).dispute_id;
+    s.client.resolve_dispute(&lease_id, &arbitrator, &20);
+    s.client.appeal(&dispute_id, &lessee);
+    s.client.resolve_dispute(&lease_id, &s.admin, &20);
+    assert_eq!(total_balance(&s, &lessee), 870);
+    assert_eq!(total_balance(&s, &owner), 130);
+}
+
+#[test]
+fn test_multisig_release_needs_threshold_agreement() {
+    let s = setup();
+    let arbitrator = Address::generate(&s.env);
+    s.client.add_arbitrator(&s.admin, &arbitrator);
+    let owner = Address::generate(&s.env);
+    let lessee = funded_account(&s.env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 100, PaymentModel::PayPerUse);
+    let lease_id = create_lease(&s, asset_id, &lessee, 3600);
+    s.client.enable_multisig_release(&lease_id, &lessee, &2);
+    s.client.process_payment(&lease_id, &lessee);
+    
+    // Approvals for different splits do not count towards each other
+    s.client.approve_release(&lease_id, &lessee, &50);
+    s.client.approve_release(&lease_id, &owner, &30);
+    assert!(s.client.get_lease(&lease_id).is_active);
+    assert!(s.client.try_approve_release(&lease_id, &Address::generate(&s.env), &30).is_err());
+    
+    s.client.approve_release(&lease_id, &arbitrator, &30);
+    assert!(!s.client.get_lease(&lease_id).is_active);
+    assert_eq!(total_balance(&s, &owner), 70);
+    assert_eq!(total_balance(&s, &lessee), 930);
+}
+
+#[test]
+fn test_deposit_returned_after_claim_window() {
+    let s = setup();
+    let env = &s.env;
+    let owner = Address::generate(env);
+    let lessee = funded_account(env, &s.token, 1000);
+    let asset_id = register_device(&s, &owner, 100);
+    s.client.set_asset_deposit(&asset_id, &owner, &200);
+    let lease_id = create_lease(&s, asset_id, &lessee, 3600);
+    assert_eq!(total_balance(&s, &lessee), 800);
+    
+    s.client.process_payment(&lease_id, &lessee);
+    s.client.end_lease(&lease_id, &lessee);
+    assert!(s.client.try_release_deposit(&lease_id).is_err());
+    
+    env.ledger().with_mut(|li| li.timestamp += 259200);
+    s.client.release_deposit(&lease_id);
+    assert_eq!(total_balance(&s, &lessee), 900);
+    assert!(s.client.get_lease(&lease_id).deposit_settled);
+}
+
+#[test]
+fn test_damage_claim_splits_deposit() {
+    let s = setup();
+    let env = &s.env;
+    let arbitrator = Address::generate(env);
+    s.client.add_arbitrator(&s.admin, &arbitrator);
+    let owner = Address::generate(env);
+    let lessee = funded_account(env, &s.token, 1000);
+    let asset_id = register_device(&s, &owner, 100);
+    s.client.set_asset_deposit(&asset_id, &owner, &200);
+    let lease_id = create_lease(&s, asset_id, &lessee, 3600);
+    s.client.end_lease(&lease_id, &owner);
+    
+    let dispute_id = s.client.file_damage_claim(&lease_id, &owner, &String::from_str(env, "ipfs://photos"));
+    s.client.resolve_dispute(&lease_id, &arbitrator, &25);
+    env.ledger().with_mut(|li| li.timestamp += 259200);
+    s.client.finalize_dispute(&dispute_id);
+    
+    assert_eq!(total_balance(&s, &lessee), 850);
+    assert_eq!(total_balance(&s, &owner), 150);
+    assert!(s.client.get_lease(&lease_id).deposit_settled);
+}
+
+#[test]
+fn test_platform_fee_collected_into_treasury() {
+    let s = setup();
+    let env = &s.env;
+    s.client.set_platform_fee(&s.admin, &500);
+    s.client.set_asset_type_fee(&s.admin, &String::from_str(env, "Physical"), &1000);
+    assert_eq!(s.client.get_platform_fee(&String::from_str(env, "Physical")), 1000);
+    assert_eq!(s.client.get_platform_fee(&String::from_str(env, "Digital")), 500);
+    
+    let owner = Address::generate(env);
+    let lessee = funded_account(env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 100, PaymentModel::PayPerUse);
+    let lease_id = create_lease(&s, asset_id, &lessee, 3600);
+    s.client.process_payment(&lease_id, &lessee);
+    s.client.end_lease(&lease_id, &lessee);
+    assert_eq!(total_balance(&s, &owner), 95);
+    assert_eq!(s.client.get_treasury_balance(&s.token), 5);
+    
+    let recipient = Address::generate(env);
+    assert!(s.client.try_withdraw_fees(&s.admin, &s.token, &recipient, &6).is_err());
+    s.client.withdraw_fees(&s.admin, &s.token, &recipient, &5);
+    assert_eq!(total_balance(&s, &recipient), 5);
+    assert_eq!(s.client.get_treasury_balance(&s.token), 0);
+}
+
+#[test]
+fn test_earnings_withdrawn_on_request() {
+    let s = setup();
+    let owner = Address::generate(&s.env);
+    let lessee = funded_account(&s.env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 100, PaymentModel::PayPerUse);
+    let lease_id = create_lease(&s, asset_id, &lessee, 3600);
+    s.client.process_payment(&lease_id, &lessee);
+    s.client.end_lease(&lease_id, &lessee);
+    
+    let token = token::Client::new(&s.env, &s.token);
+    assert_eq!(token.balance(&owner), 0);
+    assert_eq!(s.client.get_claimable(&owner).get(s.token.clone()), Some(100));
+    assert!(s.client.try_withdraw(&owner, &s.token, &101).is_err());
+    
+    assert_eq!(s.client.withdraw(&owner, &s.token, &60), 40);
+    assert_eq!(token.balance(&owner), 60);
+    assert_eq!(s.client.withdraw(&owner, &s.token, &40), 0);
+    assert_eq!(s.client.get_claimable(&owner).len(), 0);
+}
+
+#[test]
+fn test_asset_priced_in_allowlisted_token() {
+    let s = setup();
+    let env = &s.env;
+    let usdc = create_token(env);
+    let owner = Address::generate(env);
+    let lessee = funded_account(env, &usdc, 500);
+    let asset_id = register_asset(&s, &owner, 100, PaymentModel::PayPerUse);
+    assert!(s.client.try_set_asset_token(&asset_id, &owner, &usdc).is_err());
+    
+    s.client.allow_token(&s.admin, &usdc);
+    s.client.set_asset_token(&asset_id, &owner, &usdc);
+    let lease_id = create_lease(&s, asset_id, &lessee, 3600);
+    s.client.process_payment(&lease_id, &lessee);
+    assert_eq!(token::Client::new(env, &usdc).balance(&lessee), 400);
+    
+    s.client.end_lease(&lease_id, &lessee);
+    assert_eq!(s.client.get_claimable(&owner).get(usdc.clone()), Some(100));
+    assert_eq!(s.client.get_asset_stats().revenue.get(usdc), Some(100));
+    assert_eq!(s.client.get_asset_stats().revenue.get(s.token.clone()), None);
+}
+
+#[test]
+fn test_fiat_price_converted_and_rounded_up() {
+    let s = setup();
+    s.env.ledger().with_mut(|li| li.timestamp = 1000);
+    // 1 token = 0.5 USD
+    let oracle = price_oracle(&s, 50_000_000_000_000, 1000);
+    let owner = Address::generate(&s.env);
+    let lessee = funded_account(&s.env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 100, PaymentModel::PayPerUse);
+    s.client.set_asset_fiat_pricing(&asset_id, &owner, &true);
+    assert_eq!(s.client.quote_lease(&asset_id, &3600, &Vec::new(&s.env)), 200);
+    
+    // 1 token = 0.3 USD, so 100 / 0.3 = 333.33 rounds up in the owner's favour
+    oracle.set_price(&OracleAsset::Stellar(s.token.clone()), &30_000_000_000_000, &1000);
+    assert_eq!(s.client.quote_lease(&asset_id, &3600, &Vec::new(&s.env)), 334);
+    
+    // The lessee's maximum payment guards against price moves
+    let lease_call = s.client.try_create_lease(&asset_id, &lessee, &3600, &encryption_key(&s.env), &333, &None, &Vec::new(&s.env));
+    assert!(lease_call.is_err());
+    let lease_id = s.client.create_lease(&asset_id, &lessee, &3600, &encryption_key(&s.env), &334, &None, &Vec::new(&s.env));
+    assert_eq!(s.client.get_lease(&lease_id).total_cost, 334);
+}
+
+#[test]
+fn test_fiat_price_rejects_stale_oracle() {
+    let s = setup();
+    s.env.ledger().with_mut(|li| li.timestamp = 1000);
+    let oracle = price_oracle(&s, 50_000_000_000_000, 600);
+    let owner = Address::generate(&s.env);
+    let asset_id = register_asset(&s, &owner, 100, PaymentModel::PayPerUse);
+    s.client.set_asset_fiat_pricing(&asset_id, &owner, &true);
+    assert!(s.client.try_quote_lease(&asset_id, &3600, &Vec::new(&s.env)).is_err());
+    
+    oracle.set_price(&OracleAsset::Stellar(s.token.clone()), &50_000_000_000_000, &700);
+    assert_eq!(s.client.quote_lease(&asset_id, &3600, &Vec::new(&s.env)), 200);
+}
+
+#[test]
+fn test_fiat_price_rejects_overflow() {
+    let s = setup();
+    s.env.ledger().with_mut(|li| li.timestamp = 1000);
+    // 1 token = 10^-14 USD
+    price_oracle(&s, 1, 1000);
+    let owner = Address::generate(&s.env);
+    let asset_id = register_asset(&s, &owner, 1_000_000, PaymentModel::PayPerUse);
+    s.client.set_asset_fiat_pricing(&asset_id, &owner, &true);
+    assert!(s.client.try_quote_lease(&asset_id, &3600, &Vec::new(&s.env)).is_err());
+}
+
+#[test]
+fn test_streaming_settles_elapsed_time_only() {
+    let s = setup();
+    let owner = Address::generate(&s.env);
+    let lessee = funded_account(&s.env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 10, PaymentModel::Hourly);
+    s.client.set_asset_streaming(&asset_id, &owner, &true);
+    let lease_id = create_lease(&s, asset_id, &lessee, 36000);
+    s.client.process_payment(&lease_id, &lessee);
+    assert_eq!(total_balance(&s, &lessee), 900);
+    
+    s.env.ledger().with_mut(|li| li.timestamp += 9000);
+    assert_eq!(s.client.get_accrued(&lease_id), 25);
+    assert_eq!(s.client.claim_streamed(&lease_id, &owner), 25);
+    assert_eq!(s.client.get_accrued(&lease_id), 0);
+    
+    // Ending halfway through refunds the unstreamed half
+    s.env.ledger().with_mut(|li| li.timestamp += 9000);
+    s.client.end_lease(&lease_id, &lessee);
+    assert_eq!(total_balance(&s, &owner), 50);
+    assert_eq!(total_balance(&s, &lessee), 950);
+    assert_eq!(s.client.get_asset_stats().revenue.get(s.token.clone()), Some(50));
+}
+
+#[test]
+fn test_lessee_cancellation_refunds_per_policy() {
+    let s = setup();
+    let owner = Address::generate(&s.env);
+    let lessee = funded_account(&s.env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 10, PaymentModel::Hourly);
+    s.client.set_cancellation_policy(&asset_id, &owner, &CancellationPolicy::Moderate, &10);
+    let lease_id = create_lease(&s, asset_id, &lessee, 36000);
+    s.client.process_payment(&lease_id, &lessee);
+    
+    // Half of the unused half is refunded
+    s.env.ledger().with_mut(|li| li.timestamp += 18000);
+    s.client.end_lease(&lease_id, &lessee);
+    assert_eq!(total_balance(&s, &lessee), 925);
+    assert_eq!(total_balance(&s, &owner), 75);
+}
+
+#[test]
+fn test_lessor_termination_penalty_collected_from_lessor() {
+    let s = setup();
+    let owner = funded_account(&s.env, &s.token, 5000);
+    let lessee = funded_account(&s.env, &s.token, 10000);
+    let asset_id = register_asset(&s, &owner, 1000, PaymentModel::Daily);
+    s.client.set_cancellation_policy(&asset_id, &owner, &CancellationPolicy::Strict, &50);
+    let lease_id = create_lease(&s, asset_id, &lessee, 10 * 86400);
+    s.client.process_payment(&lease_id, &lessee);
+    
+    // Ending at once refunds the full payment plus half of it from the lessor's wallet
+    s.client.end_lease(&lease_id, &owner);
+    assert_eq!(s.client.get_claimable(&lessee).get(s.token.clone()), Some(15000));
+    assert_eq!(total_balance(&s, &owner), 0);
+}
+
+#[test]
+fn test_lessor_termination_penalty_drawn_from_earnings_first() {
+    let s = setup();
+    let owner = Address::generate(&s.env);
+    let lessee = funded_account(&s.env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 10, PaymentModel::Hourly);
+    s.client.set_cancellation_policy(&asset_id, &owner, &CancellationPolicy::Strict, &10);
+    let lease_id = create_lease(&s, asset_id, &lessee, 36000);
+    s.client.process_payment(&lease_id, &lessee);
+    
+    s.env.ledger().with_mut(|li| li.timestamp += 18000);
+    s.client.end_lease(&lease_id, &owner);
+    assert_eq!(total_balance(&s, &lessee), 960);
+    assert_eq!(total_balance(&s, &owner), 40);
+}
+
+#[test]
+fn test_lessor_termination_of_pay_per_use_lease() {
+    let s = setup();
+    let owner = funded_account(&s.env, &s.token, 100);
+    let lessee = funded_account(&s.env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 100, PaymentModel::PayPerUse);
+    s.client.set_cancellation_policy(&asset_id, &owner, &CancellationPolicy::Strict, &20);
+    let lease_id = create_lease(&s, asset_id, &lessee, 3600);
+    s.client.process_payment(&lease_id, &lessee);
+    
+    // A lessee cancelling a pay-per-use lease gets nothing back, but the lessor ending it early still pays
+    s.env.ledger().with_mut(|li| li.timestamp += 1800);
+    s.client.end_lease(&lease_id, &owner);
+    assert_eq!(total_balance(&s, &lessee), 970);
+    assert_eq!(total_balance(&s, &owner), 130);
+}
+
+#[test]
+fn test_lessor_termination_requires_penalty_funds() {
+    let s = setup();
+    let owner = Address::generate(&s.env);
+    let lessee = funded_account(&s.env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 100, PaymentModel::Daily);
+    s.client.set_cancellation_policy(&asset_id, &owner, &CancellationPolicy::Strict, &50);
+    let lease_id = create_lease(&s, asset_id, &lessee, 86400);
+    s.client.process_payment(&lease_id, &lessee);
+    
+    assert!(s.client.try_end_lease(&lease_id, &owner).is_err());
+    assert!(s.client.get_lease(&lease_id).is_active);
+}
+
+#[test]
+fn test_subscription_renews_then_lapses() {
+    let s = setup();
+    let owner = Address::generate(&s.env);
+    let lessee = funded_account(&s.env, &s.token, 250);
+    let asset_id = register_asset(&s, &owner, 10, PaymentModel::Hourly);
+    let lease_id = create_lease(&s, asset_id, &lessee, 36000);
+    s.client.process_payment(&lease_id, &lessee);
+    s.client.enable_subscription(&lease_id, &lessee);
+    token::Client::new(&s.env, &s.token).approve(&lessee, &s.client.address, &1000, &1000);
+    
+    let lease_ids = Vec::from_array(&s.env, [lease_id]);
+    assert_eq!(s.client.renew_due(&lease_ids), 0);
+    s.env.ledger().with_mut(|li| li.timestamp += 36000);
+    assert_eq!(s.client.renew_due(&lease_ids), 1);
+    assert_eq!(total_balance(&s, &owner), 100);
+    assert_eq!(s.client.get_lease(&lease_id).end_time, 72000);
+    
+    // The lessee cannot cover the third period, so the lease lapses after the grace period
+    s.env.ledger().with_mut(|li| li.timestamp += 36000);
+    assert_eq!(s.client.renew_due(&lease_ids), 0);
+    assert!(s.client.get_lease(&lease_id).is_active);
+    s.env.ledger().with_mut(|li| li.timestamp += 259200);
+    assert_eq!(s.client.renew_due(&lease_ids), 0);
+    assert!(!s.client.get_lease(&lease_id).is_active);
+    assert_eq!(total_balance(&s, &owner), 200);
+    assert_eq!(total_balance(&s, &lessee), 50);
+}
+
+#[test]
+fn test_subscription_ends_when_asset_token_changes() {
+    let s = setup();
+    let usdc = create_token(&s.env);
+    s.client.allow_token(&s.admin, &usdc);
+    let owner = Address::generate(&s.env);
+    let lessee = funded_account(&s.env, &s.token, 1000);
+    token::StellarAssetClient::new(&s.env, &usdc).mint(&lessee, &1000);
+    let asset_id = register_asset(&s, &owner, 10, PaymentModel::Hourly);
+    let lease_id = create_lease(&s, asset_id, &lessee, 36000);
+    s.client.process_payment(&lease_id, &lessee);
+    s.client.enable_subscription(&lease_id, &lessee);
+    token::Client::new(&s.env, &s.token).approve(&lessee, &s.client.address, &1000, &1000);
+    token::Client::new(&s.env, &usdc).approve(&lessee, &s.client.address, &1000, &1000);
+    
+    s.client.set_asset_token(&asset_id, &owner, &usdc);
+    s.env.ledger().with_mut(|li| li.timestamp += 36000);
+    assert_eq!(s.client.renew_due(&Vec::from_array(&s.env, [lease_id])), 0);
+    
+    let lease = s.client.get_lease(&lease_id);
+    assert!(!lease.is_active && !lease.auto_renew);
+    assert_eq!(total_balance(&s, &lessee), 900);
+    assert_eq!(token::Client::new(&s.env, &usdc).balance(&lessee), 1000);
+    assert_eq!(total_balance(&s, &owner), 100);
+}
+
+#[test]
+fn test_renewal_batch_skips_unknown_and_unpaid_leases() {
+    let s = setup();
+    let owner = Address::generate(&s.env);
+    let asset_id = register_asset(&s, &owner, 10, PaymentModel::Hourly);
+    let lessee = funded_account(&s.env, &s.token, 100);
+    let lease_id = create_lease(&s, asset_id, &lessee, 36000);
+    s.client.process_payment(&lease_id, &lessee);
+    s.client.enable_subscription(&lease_id, &lessee);
+    
+    let other_asset_id = register_asset(&s, &owner, 10, PaymentModel::Hourly);
+    let other_lessee = funded_account(&s.env, &s.token, 200);
+    let other_lease_id = create_lease(&s, other_asset_id, &other_lessee, 36000);
+    s.client.process_payment(&other_lease_id, &other_lessee);
+    s.client.enable_subscription(&other_lease_id, &other_lessee);
+    token::Client::new(&s.env, &s.token).approve(&other_lessee, &s.client.address, &1000, &1000);
+    
+    // The first lessee never approved an allowance, so only the second lease renews
+    s.env.ledger().with_mut(|li| li.timestamp += 36000);
+    let lease_ids = Vec::from_array(&s.env, [999, lease_id, other_lease_id]);
+    assert_eq!(s.client.renew_due(&lease_ids), 1);
+    assert_eq!(s.client.get_lease(&lease_id).end_time, 36000);
+    assert_eq!(s.client.get_lease(&other_lease_id).end_time, 72000);
+    assert_eq!(total_balance(&s, &other_lessee), 0);
+}
+
+#[test]
+fn test_tiered_pricing_with_duration_discount() {
+    let s = setup();
+    let env = &s.env;
+    let owner = Address::generate(env);
+    let asset_id = register_asset(&s, &owner, 10, PaymentModel::Hourly);
+    let tiers = Vec::from_array(env, [PriceTier { from_unit: 24, price: 5 }, PriceTier { from_unit: 48, price: 2 }]);
+    let discounts = Vec::from_array(env, [DurationDiscount { min_duration: 72 * 3600, percent_off: 10 }]);
+    s.client.set_asset_pricing(&asset_id, &owner, &tiers, &discounts);
+    
+    let no_scopes = Vec::new(env);
+    assert_eq!(s.client.quote_lease(&asset_id, &(10 * 3600), &no_scopes), 100);
+    assert_eq!(s.client.quote_lease(&asset_id, &(30 * 3600), &no_scopes), 270);
+    assert_eq!(s.client.quote_lease(&asset_id, &(50 * 3600), &no_scopes), 364);
+    // 24 * 10 + 24 * 5 + 52 * 2 = 464, less 10% rounded in the owner's favour
+    assert_eq!(s.client.quote_lease(&asset_id, &(100 * 3600), &no_scopes), 418);
+}
+
+#[test]
+fn test_coupon_discounts_lease_until_exhausted() {
+    let s = setup();
+    let env = &s.env;
+    let owner = Address::generate(env);
+    let lessee = funded_account(env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 10, PaymentModel::Hourly);
+    let code = Bytes::from_slice(env, b"SPRING");
+    let code_hash = s.client.compute_coupon_hash(&code);
+    assert!(s.client.try_get_coupon(&owner, &code_hash).is_err());
+    s.client.create_coupon(&owner, &false, &asset_id, &code_hash, &25, &1, &1000);
+    
+    let lease_id = s.client.create_lease(&asset_id, &lessee, &36000, &encryption_key(env), &75, &Some(code.clone()), &Vec::new(env));
+    assert_eq!(s.client.get_lease(&lease_id).total_cost, 75);
+    assert_eq!(s.client.get_coupon(&owner, &code_hash).uses, 1);
+    s.client.end_lease(&lease_id, &lessee);
+    
+    // The single use is spent
+    let lease_call = s.client.try_create_lease(&asset_id, &lessee, &36000, &encryption_key(env), &100, &Some(code), &Vec::new(env));
+    assert!(lease_call.is_err());
+}
+
+#[test]
+fn test_owner_wide_coupon_expires() {
+    let s = setup();
+    let env = &s.env;
+    let owner = Address::generate(env);
+    let lessee = funded_account(env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 10, PaymentModel::Hourly);
+    let code = Bytes::from_slice(env, b"WELCOME");
+    s.client.create_coupon(&owner, &true, &0, &s.client.compute_coupon_hash(&code), &50, &10, &1000);
+    
+    env.ledger().with_mut(|li| li.timestamp = 1000);
+    let lease_call = s.client.try_create_lease(&asset_id, &lessee, &36000, &encryption_key(env), &100, &Some(code), &Vec::new(env));
+    assert!(lease_call.is_err());
+}
+
+#[test]
+fn test_auction_settles_to_highest_bidder() {
+    let s = setup();
+    let env = &s.env;
+    let owner = Address::generate(env);
+    let first_bidder = funded_account(env, &s.token, 1000);
+    let second_bidder = funded_account(env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 10, PaymentModel::Hourly);
+    let auction_id = s.client.start_auction(&asset_id, &owner, &2000, &3600, &100, &1000);
+    assert!(s.client.try_get_auction(&(auction_id + 1)).is_err());
+    
+    // An outbid bidder is refunded at once
+    assert!(s.client.try_bid(&auction_id, &first_bidder, &99).is_err());
+    s.client.bid(&auction_id, &first_bidder, &100);
+    assert!(s.client.try_bid(&auction_id, &second_bidder, &100).is_err());
+    s.client.bid(&auction_id, &second_bidder, &150);
+    assert_eq!(total_balance(&s, &first_bidder), 1000);
+    assert_eq!(total_balance(&s, &second_bidder), 850);
+    
+    assert!(s.client.try_settle_auction(&auction_id).is_err());
+    env.ledger().with_mut(|li| li.timestamp = 1000);
+    let lease_id = s.client.settle_auction(&auction_id);
+    let lease = s.client.get_lease(&lease_id);
+    assert!(lease.is_paid);
+    assert_eq!(lease.lessee, second_bidder);
+    assert_eq!(lease.total_cost, 150);
+    assert_eq!(lease.start_time, 2000);
+    
+    env.ledger().with_mut(|li| li.timestamp = 6000);
+    s.client.end_lease(&lease_id, &second_bidder);
+    assert_eq!(total_balance(&s, &owner), 150);
+    assert!(s.client.get_asset(&asset_id).is_available);
+}
+
+#[test]
+fn test_countered_offer_accepted_by_lessee() {
+    let s = setup();
+    let owner = Address::generate(&s.env);
+    let lessee = funded_account(&s.env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 10, PaymentModel::Hourly);
+    let offer_id = s.client.make_offer(&asset_id, &lessee, &200, &1000, &36000, &500);
+    assert_eq!(total_balance(&s, &lessee), 800);
+    
+    // Once countered only the lessee can accept, topping up the escrow
+    s.client.counter_offer(&offer_id, &owner, &300, &1000, &36000, &600);
+    assert!(s.client.try_accept_offer(&offer_id, &owner).is_err());
+    let lease_id = s.client.accept_offer(&offer_id, &lessee);
+    assert_eq!(total_balance(&s, &lessee), 700);
+    assert!(s.client.get_offer(&offer_id).status == OfferStatus::Accepted);
+    assert!(s.client.get_lease(&lease_id).is_paid);
+    assert!(!s.client.get_asset(&asset_id).is_available);
+}
+
+#[test]
+fn test_rejected_offer_refunds_lessee() {
+    let s = setup();
+    let owner = Address::generate(&s.env);
+    let lessee = funded_account(&s.env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 10, PaymentModel::Hourly);
+    let offer_id = s.client.make_offer(&asset_id, &lessee, &100, &1000, &36000, &500);
+    assert_eq!(total_balance(&s, &lessee), 900);
+    
+    s.client.reject_offer(&offer_id, &owner);
+    assert_eq!(total_balance(&s, &lessee), 1000);
+    assert!(s.client.try_accept_offer(&offer_id, &owner).is_err());
+    assert!(s.client.try_get_offer(&(offer_id + 1)).is_err());
+}
+
+#[test]
+fn test_pending_lease_refunded_on_timeout() {
+    let s = setup();
+    let owner = Address::generate(&s.env);
+    let lessee = funded_account(&s.env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 10, PaymentModel::Hourly);
+    s.client.set_asset_requires_approval(&asset_id, &owner, &true);
+    let lease_id = create_lease(&s, asset_id, &lessee, 36000);
+    assert!(s.client.get_lease(&lease_id).is_pending);
+    assert_eq!(total_balance(&s, &lessee), 900);
+    
+    // Only the owner can decline before the deadline, anyone after it
+    let anyone = Address::generate(&s.env);
+    assert!(s.client.try_decline_lease(&lease_id, &anyone).is_err());
+    s.env.ledger().with_mut(|li| li.timestamp = 172800);
+    s.client.decline_lease(&lease_id, &anyone);
+    assert_eq!(total_balance(&s, &lessee), 1000);
+    assert!(s.client.get_asset(&asset_id).is_available);
+}
+
+#[test]
+fn test_approved_lease_runs_from_approval() {
+    let s = setup();
+    let owner = Address::generate(&s.env);
+    let lessee = funded_account(&s.env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 10, PaymentModel::Hourly);
+    s.client.set_asset_requires_approval(&asset_id, &owner, &true);
+    let lease_id = create_lease(&s, asset_id, &lessee, 36000);
+    
+    s.env.ledger().with_mut(|li| li.timestamp += 100);
+    s.client.approve_lease(&lease_id, &owner);
+    let lease = s.client.get_lease(&lease_id);
+    assert!(lease.is_active && !lease.is_pending);
+    assert_eq!(lease.start_time, 100);
+    assert_eq!(lease.end_time, 36100);
+    
+    s.env.ledger().with_mut(|li| li.timestamp += 36000);
+    s.client.end_lease(&lease_id, &lessee);
+    assert_eq!(total_balance(&s, &owner), 100);
+}
+
+#[test]
+fn test_allowlist_only_owner_permits_listed_lessees() {
+    let s = setup();
+    let owner = Address::generate(&s.env);
+    let partner = funded_account(&s.env, &s.token, 1000);
+    let stranger = funded_account(&s.env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 10, PaymentModel::Hourly);
+    s.client.set_allowlist_only(&owner, &true, &0, &true);
+    assert!(!s.client.is_lessee_permitted(&asset_id, &partner));
+    
+    s.client.allow_lessee(&owner, &false, &asset_id, &partner, &true);
+    assert!(s.client.is_lessee_permitted(&asset_id, &partner));
+    assert!(!s.client.is_lessee_permitted(&asset_id, &stranger));
+    
+    // A block overrides the allowlist
+    s.client.block_lessee(&owner, &true, &0, &partner, &true);
+    assert!(!s.client.is_lessee_permitted(&asset_id, &partner));
+    s.client.block_lessee(&owner, &true, &0, &partner, &false);
+    
+    let lease_call = s.client.try_create_lease(&asset_id, &stranger, &3600, &encryption_key(&s.env), &100, &None, &Vec::new(&s.env));
+    assert!(lease_call.is_err());
+    create_lease(&s, asset_id, &partner, 3600);
+}
+
+#[test]
+fn test_blocked_lessee_cannot_lease() {
+    let s = setup();
+    let owner = Address::generate(&s.env);
+    let lessee = funded_account(&s.env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 10, PaymentModel::Hourly);
+    s.client.block_lessee(&owner, &false, &asset_id, &lessee, &true);
+    
+    let lease_call = s.client.try_create_lease(&asset_id, &lessee, &3600, &encryption_key(&s.env), &100, &None, &Vec::new(&s.env));
+    assert!(lease_call.is_err());
+    assert!(s.client.get_asset(&asset_id).is_available);
+}
+
+#[test]
+fn test_credential_required_to_lease_bid_and_offer() {
+    let s = setup();
+    let env = &s.env;
+    let attestation_id = env.register_contract(None, testutils::MockAttestation);
+    let attestation = testutils::MockAttestationClient::new(env, &attestation_id);
+    s.client.set_attestation_contract(&s.admin, &attestation_id);
+    
+    let owner = Address::generate(env);
+    let lessee = funded_account(env, &s.token, 1000);
+    let kyc = symbol_short!("KYC");
+    let lease_asset_id = register_asset(&s, &owner, 10, PaymentModel::Hourly);
+    let auction_asset_id = register_asset(&s, &owner, 10, PaymentModel::Hourly);
+    let offer_asset_id = register_asset(&s, &owner, 10, PaymentModel::Hourly);
+    for asset_id in [lease_asset_id, auction_asset_id, offer_asset_id] {
+        s.client.set_asset_credential(&asset_id, &owner, &true, &kyc);
+    }
+    let auction_id = s.client.start_auction(&auction_asset_id, &owner, &2000, &3600, &100, &1000);
+    
+    // Without the credential every way of obtaining a lease is refused
+    assert!(!s.client.is_lessee_permitted(&lease_asset_id, &lessee));
+    let lease_call = s.client.try_create_lease(&lease_asset_id, &lessee, &3600, &encryption_key(env), &100, &None, &Vec::new(env));
+    assert!(lease_call.is_err());
+    assert!(s.client.try_bid(&auction_id, &lessee, &100).is_err());
+    assert!(s.client.try_make_offer(&offer_asset_id, &lessee, &100, &1000, &3600, &500).is_err());
+    assert_eq!(total_balance(&s, &lessee), 1000);
+    
+    attestation.set_credential(&lessee, &kyc, &true);
+    assert!(s.client.is_lessee_permitted(&lease_asset_id, &lessee));
+    create_lease(&s, lease_asset_id, &lessee, 3600);
+    s.client.bid(&auction_id, &lessee, &100);
+    s.client.make_offer(&offer_asset_id, &lessee, &100, &1000, &3600, &500);
+    assert_eq!(total_balance(&s, &lessee), 800);
+}
+
+#[test]
+fn test_access_key_delivered_rotated_and_revoked() {
+    let s = setup();
+    let env = &s.env;
+    let owner = Address::generate(env);
+    let lessee = funded_account(env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 10, PaymentModel::Hourly);
+    let lease_id = create_lease(&s, asset_id, &lessee, 3600);
+    assert!(s.client.try_get_access_key(&lease_id, &lessee).is_err());
+    
+    let first_key = Bytes::from_slice(env, b"key sealed to the lessee");
+    assert_eq!(s.client.deliver_access_key(&lease_id, &owner, &first_key), 1);
+    assert_eq!(s.client.get_access_key(&lease_id, &lessee), first_key);
+    assert!(s.client.try_get_access_key(&lease_id, &owner).is_err());
+    
+    let second_key = Bytes::from_slice(env, b"rotated key sealed to the lessee");
+    assert_eq!(s.client.rotate_access_key(&lease_id, &owner, &second_key), 2);
+    assert_eq!(s.client.get_access_key(&lease_id, &lessee), second_key);
+    
+    s.client.revoke_access(&lease_id, &owner);
+    assert!(s.client.get_lease(&lease_id).access_revoked);
+    assert!(s.client.try_get_access_key(&lease_id, &lessee).is_err());
+}
+
+#[test]
+fn test_verify_access_honours_payment_and_disputes() {
+    let s = setup();
+    let owner = Address::generate(&s.env);
+    let lessee = funded_account(&s.env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 10, PaymentModel::Hourly);
+    let lease_id = create_lease(&s, asset_id, &lessee, 3600);
+    assert!(s.client.verify_access(&asset_id, &lessee, &0).is_none());
+    
+    s.client.process_payment(&lease_id, &lessee);
+    let grant = s.client.verify_access(&asset_id, &lessee, &0).unwrap();
+    assert_eq!(grant.lease_id, lease_id);
+    assert_eq!(grant.expires_at, 3600);
+    assert!(s.client.verify_access(&asset_id, &lessee, &3600).is_none());
+    
+    s.client.raise_dispute(&lease_id, &lessee);
+    assert!(s.client.verify_access(&asset_id, &lessee, &0).is_none());
+}
+
+#[test]
+fn test_verify_access_for_future_lease_window() {
+    let s = setup();
+    let owner = Address::generate(&s.env);
+    let bidder = funded_account(&s.env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 10, PaymentModel::Hourly);
+    let auction_id = s.client.start_auction(&asset_id, &owner, &2000, &3600, &100, &1000);
+    s.client.bid(&auction_id, &bidder, &100);
+    s.env.ledger().with_mut(|li| li.timestamp = 1000);
+    let lease_id = s.client.settle_auction(&auction_id);
+    
+    // The won window has not started yet
+    assert!(s.client.verify_access(&asset_id, &bidder, &1000).is_none());
+    assert_eq!(s.client.verify_access(&asset_id, &bidder, &2000).unwrap().lease_id, lease_id);
+    assert!(s.client.verify_access(&asset_id, &bidder, &5600).is_none());
+    
+    s.env.ledger().with_mut(|li| li.timestamp = 5600);
+    s.client.end_lease(&lease_id, &bidder);
+    assert!(s.client.verify_access(&asset_id, &bidder, &2000).is_none());
+}
+
+#[test]
+fn test_access_token_validated_until_revoked() {
+    let s = setup();
+    let owner = Address::generate(&s.env);
+    let lessee = funded_account(&s.env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 10, PaymentModel::Hourly);
+    let lease_id = create_lease(&s, asset_id, &lessee, 3600);
+    s.client.process_payment(&lease_id, &lessee);
+    let signing_key = SigningKey::from_bytes(&[7; 32]);
+    s.client.set_signing_key(&lease_id, &lessee, &BytesN::from_array(&s.env, &signing_key.verifying_key().to_bytes()));
+    
+    let token = AccessToken { lease_id, asset_id, lessee: lessee.clone(), expiry: 1000, nonce: 1 };
+    let signature = sign_access_token(&s, &signing_key, &token);
+    assert_eq!(s.client.validate_access_token(&token, &signature).unwrap().lease_id, lease_id);
+    
+    // Tokens are checked against their nonce and expiry
+    let other_token = AccessToken { nonce: 2, ..token.clone() };
+    let other_signature = sign_access_token(&s, &signing_key, &other_token);
+    s.client.revoke_access_token(&token, &owner);
+    assert!(s.client.validate_access_token(&token, &signature).is_none());
+    assert!(s.client.validate_access_token(&other_token, &other_signature).is_some());
+    s.env.ledger().with_mut(|li| li.timestamp = 1000);
+    assert!(s.client.validate_access_token(&other_token, &other_signature).is_none());
+}
+
+#[test]
+fn test_access_token_signature_bound_to_contract() {
+    let s = setup();
+    let owner = Address::generate(&s.env);
+    let lessee = funded_account(&s.env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 10, PaymentModel::Hourly);
+    let lease_id = create_lease(&s, asset_id, &lessee, 3600);
+    s.client.process_payment(&lease_id, &lessee);
+    let signing_key = SigningKey::from_bytes(&[7; 32]);
+    s.client.set_signing_key(&lease_id, &lessee, &BytesN::from_array(&s.env, &signing_key.verifying_key().to_bytes()));
+    
+    // A signature over the bare token, as another deployment would accept, is rejected
+    let token = AccessToken { lease_id, asset_id, lessee: lessee.clone(), expiry: 1000, nonce: 1 };
+    let mut message = std::vec![0u8; token.clone().to_xdr(&s.env).len() as usize];
+    token.clone().to_xdr(&s.env).copy_into_slice(&mut message);
+    let bare_signature = BytesN::from_array(&s.env, &signing_key.sign(&message).to_bytes());
+    assert!(s.client.try_validate_access_token(&token, &bare_signature).is_err());
+}
+
+#[test]
+fn test_token_revocation_kept_until_expiry() {
+    let s = setup();
+    let owner = Address::generate(&s.env);
+    let lessee = funded_account(&s.env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 10, PaymentModel::Hourly);
+    let lease_id = create_lease(&s, asset_id, &lessee, 3600);
+    
+    // The revocation lives as long as the revoked token; far-future expiries are capped at the network maximum
+    let token = AccessToken { lease_id, asset_id, lessee: lessee.clone(), expiry: 100_000, nonce: 1 };
+    let long_lived_token = AccessToken { expiry: u64::MAX, nonce: 2, ..token.clone() };
+    s.client.revoke_access_token(&token, &lessee);
+    s.client.revoke_access_token(&long_lived_token, &owner);
+    
+    // Revoking the same nonce again never shortens the revocation
+    s.client.revoke_access_token(&AccessToken { expiry: 10, ..token.clone() }, &lessee);
+    s.env.as_contract(&s.client.address, || {
+        let storage = s.env.storage().persistent();
+        assert_eq!(storage.get_ttl(&LeaseRegistry::RevokedToken(lease_id, 1)), 20001);
+        assert!(storage.get_ttl(&LeaseRegistry::RevokedToken(lease_id, 2)) > 20001);
+        assert!(!s.env.storage().instance().has(&LeaseRegistry::RevokedToken(lease_id, 1)));
+    });
+}
+
+#[test]
+fn test_capability_scoped_lease_priced_per_capability() {
+    let s = setup();
+    let env = &s.env;
+    let owner = Address::generate(env);
+    let lessee = funded_account(env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 10, PaymentModel::Hourly);
+    let raw = symbol_short!("raw");
+    let control = symbol_short!("control");
+    let capabilities = Vec::from_array(env, [
+        Capability { name: raw.clone(), price: 2 },
+        Capability { name: control.clone(), price: 5 }
+    ]);
+    s.client.set_asset_capabilities(&asset_id, &owner, &capabilities);
+    
+    // Full access includes every capability
+    assert_eq!(s.client.quote_lease(&asset_id, &36000, &Vec::new(env)), 170);
+    let scopes = Vec::from_array(env, [raw.clone()]);
+    assert_eq!(s.client.quote_lease(&asset_id, &36000, &scopes), 120);
+    assert!(s.client.try_quote_lease(&asset_id, &36000, &Vec::from_array(env, [symbol_short!("admin")])).is_err());
+    
+    let lease_id = s.client.create_lease(&asset_id, &lessee, &36000, &encryption_key(env), &120, &None, &scopes);
+    s.client.process_payment(&lease_id, &lessee);
+    assert_eq!(s.client.verify_access(&asset_id, &lessee, &0).unwrap().scopes, scopes);
+}
+
+#[test]
+fn test_sublease_terminated_with_disputed_parent() {
+    let s = setup();
+    let owner = Address::generate(&s.env);
+    let reseller = funded_account(&s.env, &s.token, 1000);
+    let sublessee = funded_account(&s.env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 10, PaymentModel::Hourly);
+    let lease_id = create_lease(&s, asset_id, &reseller, 36000);
+    s.client.process_payment(&lease_id, &reseller);
+    assert!(s.client.try_sublease(&lease_id, &reseller, &sublessee, &18000, &80).is_err());
+    
+    s.client.set_asset_sublease(&asset_id, &owner, &true, &20);
+    let sublease_id = s.client.sublease(&lease_id, &reseller, &sublessee, &18000, &80);
+    assert_eq!(s.client.verify_access(&asset_id, &sublessee, &0).unwrap().lease_id, sublease_id);
+    
+    s.env.ledger().with_mut(|li| li.timestamp = 9000);
+    s.client.raise_dispute(&lease_id, &reseller);
+    assert!(s.client.verify_access(&asset_id, &sublessee, &9000).is_none());
+    assert!(!s.client.get_lease(&sublease_id).is_active);
+    
+    // Half the sublease elapsed: 40 is refunded and 40 split 8 to the owner, 32 to the reseller
+    assert_eq!(total_balance(&s, &sublessee), 960);
+    assert_eq!(total_balance(&s, &owner), 8);
+    assert_eq!(total_balance(&s, &reseller), 932);
+}
+
+#[test]
+fn test_pending_lease_deposit_held_until_declined() {
+    let s = setup();
+    let owner = Address::generate(&s.env);
+    let lessee = funded_account(&s.env, &s.token, 1000);
+    let asset_id = register_device(&s, &owner, 100);
+    s.client.set_asset_deposit(&asset_id, &owner, &200);
+    s.client.set_asset_requires_approval(&asset_id, &owner, &true);
+    let lease_id = create_lease(&s, asset_id, &lessee, 3600);
+    assert_eq!(total_balance(&s, &lessee), 700);
+    
+    // The claim window of a request that never closed has not started
+    s.env.ledger().with_mut(|li| li.timestamp = 259200);
+    assert!(s.client.try_release_deposit(&lease_id).is_err());
+    let evidence = String::from_str(&s.env, "ipfs://photos");
+    assert!(s.client.try_file_damage_claim(&lease_id, &owner, &evidence).is_err());
+    
+    s.client.decline_lease(&lease_id, &owner);
+    assert_eq!(total_balance(&s, &lessee), 1000);
+    assert!(s.client.try_release_deposit(&lease_id).is_err());
+}
+
+#[test]
+fn test_sublessee_limited_to_one_live_sublease() {
+    let s = setup();
+    let owner = Address::generate(&s.env);
+    let reseller = funded_account(&s.env, &s.token, 1000);
+    let sublessee = funded_account(&s.env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 10, PaymentModel::Hourly);
+    s.client.set_asset_sublease(&asset_id, &owner, &true, &20);
+    let lease_id = create_lease(&s, asset_id, &reseller, 36000);
+    s.client.process_payment(&lease_id, &reseller);
+    
+    let sublease_id = s.client.sublease(&lease_id, &reseller, &sublessee, &3600, &10);
+    assert!(s.client.try_sublease(&lease_id, &reseller, &sublessee, &3600, &10).is_err());
+    
+    // A second sublease is possible once the first has closed
+    s.client.end_lease(&sublease_id, &sublessee);
+    let next_sublease_id = s.client.sublease(&lease_id, &reseller, &sublessee, &3600, &10);
+    assert_eq!(s.client.verify_access(&asset_id, &sublessee, &0).unwrap().lease_id, next_sublease_id);
+}
+
+#[test]
+fn test_closing_lease_keeps_index_of_other_lease() {
+    let s = setup();
+    let owner = Address::generate(&s.env);
+    let lessee = funded_account(&s.env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 10, PaymentModel::Hourly);
+    let lease_id = create_lease(&s, asset_id, &lessee, 3600);
+    let key = LeaseRegistry::Active(asset_id, lessee.clone());
+    
+    // Point the index at a different lease before this one closes
+    s.env.as_contract(&s.client.address, || {
+        s.env.storage().instance().set(&key, &(lease_id + 1));
+    });
+    s.client.end_lease(&lease_id, &lessee);
+    s.env.as_contract(&s.client.address, || {
+        assert_eq!(s.env.storage().instance().get::<_, u64>(&key), Some(lease_id + 1));
+    });
+}
+
+#[test]
+fn test_renewal_batch_skips_fiat_lease_with_stale_price() {
+    let s = setup();
+    // 1 token = 0.5 USD
+    let oracle = price_oracle(&s, 50_000_000_000_000, 0);
+    let owner = Address::generate(&s.env);
+    let fiat_lessee = funded_account(&s.env, &s.token, 1000);
+    let fiat_asset_id = register_asset(&s, &owner, 10, PaymentModel::Hourly);
+    s.client.set_asset_fiat_pricing(&fiat_asset_id, &owner, &true);
+    let fiat_lease_id = create_lease(&s, fiat_asset_id, &fiat_lessee, 36000);
+    s.client.process_payment(&fiat_lease_id, &fiat_lessee);
+    s.client.enable_subscription(&fiat_lease_id, &fiat_lessee);
+    token::Client::new(&s.env, &s.token).approve(&fiat_lessee, &s.client.address, &1000, &1000);
+    
+    let lessee = funded_account(&s.env, &s.token, 1000);
+    let asset_id = register_asset(&s, &owner, 10, PaymentModel::Hourly);
+    let lease_id = create_lease(&s, asset_id, &lessee, 36000);
+    s.client.process_payment(&lease_id, &lessee);
+    s.client.enable_subscription(&lease_id, &lessee);
+    token::Client::new(&s.env, &s.token).approve(&lessee, &s.client.address, &1000, &1000);
+    
+    // The oracle price is now stale, so only the token-priced lease renews
+    s.env.ledger().with_mut(|li| li.timestamp = 36000);
+    let lease_ids = Vec::from_array(&s.env, [fiat_lease_id, lease_id]);
+    assert_eq!(s.client.renew_due(&lease_ids), 1);
+    assert_eq!(s.client.get_lease(&lease_id).end_time, 72000);
+    assert_eq!(s.client.get_lease(&fiat_lease_id).end_time, 36000);
+    assert!(s.client.get_lease(&fiat_lease_id).is_active);
+    assert_eq!(total_balance(&s, &fiat_lessee), 800);
+    
+    // A fresh price within the grace period lets the fiat lease renew
+    oracle.set_price(&OracleAsset::Stellar(s.token.clone()), &50_000_000_000_000, &36000);
+    assert_eq!(s.client.renew_due(&Vec::from_array(&s.env, [fiat_lease_id])), 1);
+    assert_eq!(total_balance(&s, &fiat_lessee), 600);
+}
@@ -0,0 +1,57 @@
+// Mock contracts for exercising the marketplace against external interfaces in tests
+use soroban_sdk::{contract, contracttype, contractimpl, Address, Env, Symbol};
+
+use crate::{OracleAsset, PriceData};
+
+// Mapping oracle asset to its latest mock price
+#[contracttype]
+pub enum MockOracleKey {
+    Price(OracleAsset)
+}
+
+// Mock SEP-40 price oracle with prices set directly by the test
+#[contract]
+pub struct MockPriceOracle;
+
+#[contractimpl]
+impl MockPriceOracle {
+    
+    // Set the latest price of an asset
+    pub fn set_price(env: Env, asset: OracleAsset, price: i128, timestamp: u64) {
+        env.storage().instance().set(&MockOracleKey::Price(asset), &PriceData { price, timestamp });
+    }
+    
+    // Get the latest price of an asset
+    pub fn lastprice(env: Env, asset: OracleAsset) -> Option<PriceData> {
+        env.storage().instance().get(&MockOracleKey::Price(asset))
+    }
+    
+    // Prices are scaled by 10^14, as on the public Stellar oracles
+    pub fn decimals(_env: Env) -> u32 {
+        14
+    }
+}
+
+// Mapping (address, credential type) to whether the mock attestation holds it
+#[contracttype]
+pub enum MockAttestationKey {
+    Credential(Address, Symbol)
+}
+
+// Mock identity attestation contract with credentials granted directly by the test
+#[contract]
+pub struct MockAttestation;
+
+#[contractimpl]
+impl MockAttestation {
+    
+    // Grant or revoke a credential for an address
+    pub fn set_credential(env: Env, address: Address, credential_type: Symbol, granted: bool) {
+        env.storage().instance().set(&MockAttestationKey::Credential(address, credential_type), &granted);
+    }
+    
+    // Check whether an address holds a credential
+    pub fn has_credential(env: Env, address: Address, credential_type: Symbol) -> bool {
+        env.storage().instance().get(&MockAttestationKey::Credential(address, credential_type)).unwrap_or(false)
+    }
+}